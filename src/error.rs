@@ -0,0 +1,89 @@
+//! Errors thrown by the wasm API.
+
+use js_sys::Reflect;
+use wasm_bindgen::JsValue;
+
+use crate::rtree::ValidationError;
+
+/// What went wrong, available to JS as the `kind` property of thrown errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    InvalidBbox,
+    InvalidCoordinate,
+    InvalidGeometry,
+    // Payload or numeric argument that can't be used, like a negative radius
+    InvalidValue,
+    InvalidSnapshot,
+    // IndexedDB unavailable, blocked, or failing a request
+    Storage,
+    // A bug on our side, like a cache left poisoned by an earlier panic
+    Internal,
+}
+
+impl ErrorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::InvalidBbox => "InvalidBbox",
+            ErrorKind::InvalidCoordinate => "InvalidCoordinate",
+            ErrorKind::InvalidGeometry => "InvalidGeometry",
+            ErrorKind::InvalidValue => "InvalidValue",
+            ErrorKind::InvalidSnapshot => "InvalidSnapshot",
+            ErrorKind::Storage => "Storage",
+            ErrorKind::Internal => "Internal",
+        }
+    }
+}
+
+/// Thrown to JS as an `Error` named `CacheError`, with extra `kind` and `input` properties.
+/// `input` is the offending argument, `undefined` if there's none
+#[derive(Debug)]
+pub struct CacheError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub input: JsValue,
+}
+
+impl CacheError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>, input: impl Into<JsValue>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            input: input.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for CacheError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.kind.as_str(), self.message)
+    }
+}
+impl std::error::Error for CacheError {}
+
+// For inputs already validated by the wasm layer, so the offending argument isn't known here
+impl From<ValidationError> for CacheError {
+    fn from(error: ValidationError) -> Self {
+        let kind = match error {
+            ValidationError::InvalidDistance(_) | ValidationError::ExceedsLimits(_) => {
+                ErrorKind::InvalidValue
+            }
+            ValidationError::EmptyPolygon => ErrorKind::InvalidGeometry,
+            _ if error.is_coordinate_error() => ErrorKind::InvalidCoordinate,
+            _ => ErrorKind::InvalidBbox,
+        };
+
+        Self::new(kind, error.to_string(), JsValue::UNDEFINED)
+    }
+}
+
+impl From<CacheError> for JsValue {
+    fn from(error: CacheError) -> Self {
+        let js_error = js_sys::Error::new(&error.message);
+        js_error.set_name("CacheError");
+        // Setting properties on a fresh object can't fail
+        let _ = Reflect::set(&js_error, &"kind".into(), &error.kind.as_str().into());
+        let _ = Reflect::set(&js_error, &"input".into(), &error.input);
+
+        js_error.into()
+    }
+}
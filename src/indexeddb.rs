@@ -0,0 +1,266 @@
+//! IndexedDB persistence for the global cache.
+//!
+//! Each entry is stored as its own record, so `persist` only writes what changed since the previous call.
+
+use js_sys::{Array, Function, Promise, Reflect, Uint8Array};
+use wasm_bindgen::{closure::Closure, prelude::wasm_bindgen, JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{IdbDatabase, IdbFactory, IdbRequest, IdbTransaction, IdbTransactionMode};
+
+use crate::{
+    error::{CacheError, ErrorKind},
+    lock,
+    rtree::{CacheChanges, CoordinateCache},
+    R_TREE,
+};
+
+const DB_NAME: &str = "wasm-rtree-cache";
+const DB_VERSION: u32 = 1;
+const ENTRIES_STORE: &str = "entries";
+const METADATA_STORE: &str = "metadata";
+const METADATA_KEY: &str = "cache";
+
+// Encoded metadata and entries, as read from the stores
+type StoredParts = (Vec<u8>, Vec<Vec<u8>>);
+
+/// Writes entries changed since the last `persist` (or `restore`) to IndexedDB
+#[wasm_bindgen]
+pub async fn persist() -> Result<(), JsValue> {
+    // Opened before taking the changes, which would be lost if opening failed afterwards
+    let db = open().await?;
+    let changes = match take_changes() {
+        Ok(changes) => changes,
+        Err(e) => {
+            db.close();
+            return Err(e.into());
+        }
+    };
+
+    let result = async {
+        let transaction = db.transaction_with_str_sequence_and_mode(
+            &store_names(),
+            IdbTransactionMode::Readwrite,
+        )?;
+        let entries = transaction.object_store(ENTRIES_STORE)?;
+        let metadata = transaction.object_store(METADATA_STORE)?;
+
+        if changes.cleared {
+            entries.clear()?;
+        }
+
+        for id in changes.removed.iter() {
+            entries.delete(&JsValue::from_f64(*id as f64))?;
+        }
+
+        for (id, bytes) in changes.upserted.iter() {
+            entries.put_with_key(
+                &Uint8Array::from(bytes.as_slice()),
+                &JsValue::from_f64(*id as f64),
+            )?;
+        }
+
+        metadata.put_with_key(
+            &Uint8Array::from(changes.metadata.as_slice()),
+            &JsValue::from_str(METADATA_KEY),
+        )?;
+
+        transaction_done(&transaction).await
+    }
+    .await
+    .map_err(storage_error);
+    db.close();
+
+    if result.is_err() {
+        // The batch is lost, so the next persist has to rewrite everything
+        if let Ok(mut handle) = lock(&R_TREE) {
+            handle.inner.track_changes();
+        }
+    }
+
+    Ok(result?)
+}
+
+// Changes since the last call, every entry on the first one
+fn take_changes() -> Result<CacheChanges, CacheError> {
+    let r_tree = &mut lock(&R_TREE)?.inner;
+
+    if !r_tree.is_tracking_changes() {
+        r_tree.track_changes();
+    }
+
+    r_tree
+        .take_changes()
+        .map_err(|e| CacheError::new(ErrorKind::Internal, e.to_string(), JsValue::UNDEFINED))
+}
+
+/// Replaces the cache with the one stored in IndexedDB. Resolves to false if nothing was persisted yet
+#[wasm_bindgen]
+pub async fn restore() -> Result<bool, JsValue> {
+    let db = open().await?;
+    let result: Result<Option<StoredParts>, JsValue> = async {
+        let transaction = db.transaction_with_str_sequence(&store_names())?;
+
+        // Requests on a transaction complete in order, so the metadata is ready once the entries are
+        let metadata = transaction
+            .object_store(METADATA_STORE)?
+            .get(&JsValue::from_str(METADATA_KEY))?;
+        let entries = transaction.object_store(ENTRIES_STORE)?.get_all()?;
+
+        let entries: Array = request_done(&entries).await?.dyn_into()?;
+        let metadata = metadata.result()?;
+
+        if metadata.is_undefined() {
+            return Ok(None);
+        }
+
+        let metadata = Uint8Array::new(&metadata).to_vec();
+        let entries = entries
+            .iter()
+            .map(|bytes| Uint8Array::new(&bytes).to_vec())
+            .collect::<Vec<_>>();
+
+        Ok(Some((metadata, entries)))
+    }
+    .await;
+    db.close();
+
+    let (metadata, entries) = match result.map_err(storage_error)? {
+        Some(parts) => parts,
+        None => return Ok(false),
+    };
+
+    let cache = CoordinateCache::from_parts(&metadata, entries).map_err(|e| {
+        CacheError::new(
+            ErrorKind::InvalidSnapshot,
+            e.to_string(),
+            JsValue::UNDEFINED,
+        )
+    })?;
+    lock(&R_TREE)?.replace(cache);
+
+    Ok(true)
+}
+
+async fn open() -> Result<IdbDatabase, CacheError> {
+    // Works on both windows and workers, unlike `Window::indexed_db`
+    let factory: IdbFactory = Reflect::get(&js_sys::global(), &JsValue::from_str("indexedDB"))
+        .ok()
+        .and_then(|factory| factory.dyn_into().ok())
+        .ok_or_else(|| {
+            let message = "IndexedDB is not available";
+            CacheError::new(ErrorKind::Storage, message, JsValue::UNDEFINED)
+        })?;
+
+    let request = factory
+        .open_with_u32(DB_NAME, DB_VERSION)
+        .map_err(storage_error)?;
+
+    let upgrade_request = request.clone();
+    let on_upgrade_needed = Closure::once(move |_: JsValue| {
+        if let Ok(db) = upgrade_request
+            .result()
+            .and_then(|db| db.dyn_into::<IdbDatabase>())
+        {
+            let _ = db.create_object_store(ENTRIES_STORE);
+            let _ = db.create_object_store(METADATA_STORE);
+        }
+    });
+    request.set_onupgradeneeded(Some(on_upgrade_needed.as_ref().unchecked_ref()));
+
+    // Another tab holding an older version open blocks the upgrade, possibly forever
+    let result = wait_for(|on_success, on_error| {
+        request.set_onsuccess(on_success);
+        request.set_onerror(on_error);
+        request.set_onblocked(on_error);
+    })
+    .await;
+    request.set_onupgradeneeded(None);
+    result.map_err(storage_error)?;
+
+    request
+        .result()
+        .and_then(|db| db.dyn_into())
+        .map_err(storage_error)
+}
+
+// IndexedDB fails with DOMExceptions, or with events whose target holds one
+fn storage_error(error: JsValue) -> CacheError {
+    let get = |value: &JsValue, key: &str| Reflect::get(value, &key.into()).ok();
+
+    if get(&error, "type").and_then(|t| t.as_string()).as_deref() == Some("blocked") {
+        let message = "IndexedDB upgrade is blocked by a connection open in another tab";
+        return CacheError::new(ErrorKind::Storage, message, error);
+    }
+
+    let exception = get(&error, "target")
+        .and_then(|target| get(&target, "error"))
+        .filter(|exception| exception.is_object())
+        .unwrap_or(error);
+    let message = get(&exception, "message")
+        .and_then(|message| message.as_string())
+        .filter(|message| !message.is_empty())
+        .unwrap_or_else(|| "IndexedDB request failed".to_string());
+
+    CacheError::new(ErrorKind::Storage, message, exception)
+}
+
+fn store_names() -> Array {
+    Array::of2(
+        &JsValue::from_str(ENTRIES_STORE),
+        &JsValue::from_str(METADATA_STORE),
+    )
+}
+
+// Resolves to the request result
+async fn request_done(request: &IdbRequest) -> Result<JsValue, JsValue> {
+    wait_for(|on_success, on_error| {
+        request.set_onsuccess(on_success);
+        request.set_onerror(on_error);
+    })
+    .await?;
+
+    request.result()
+}
+
+// Failed commits, like running out of quota, only fire `abort`
+async fn transaction_done(transaction: &IdbTransaction) -> Result<(), JsValue> {
+    wait_for(|on_complete, on_error| {
+        transaction.set_oncomplete(on_complete);
+        transaction.set_onerror(on_error);
+        transaction.set_onabort(on_error);
+    })
+    .await
+    .map(|_| ())
+}
+
+// Registers success and error handlers through `set_handlers`, resolving once either fires.
+// The error handler may be registered for several events, which can all fire, so handlers can be called
+// more than once. They're unregistered afterwards so the closures can be dropped
+async fn wait_for(
+    set_handlers: impl Fn(Option<&Function>, Option<&Function>),
+) -> Result<JsValue, JsValue> {
+    let mut handlers = None;
+    let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+        // Only the first call settles the promise
+        let on_success = Closure::<dyn FnMut(JsValue)>::new(move |event: JsValue| {
+            let _ = resolve.call1(&JsValue::UNDEFINED, &event);
+        });
+        let on_error = Closure::<dyn FnMut(JsValue)>::new(move |event: JsValue| {
+            let _ = reject.call1(&JsValue::UNDEFINED, &event);
+        });
+        handlers = Some((on_success, on_error));
+    });
+
+    // The promise executor runs synchronously, so the handlers are always set here
+    let (on_success, on_error) =
+        handlers.ok_or_else(|| JsValue::from_str("Promise executor did not run"))?;
+    set_handlers(
+        Some(on_success.as_ref().unchecked_ref()),
+        Some(on_error.as_ref().unchecked_ref()),
+    );
+
+    let result = JsFuture::from(promise).await;
+    set_handlers(None, None);
+
+    result
+}
@@ -1,17 +1,78 @@
-use std::{convert::TryInto, sync::Mutex};
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryInto,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
 
+use error::{CacheError, ErrorKind};
+use js_sys::{Array, Int32Array, Object, Reflect};
 use once_cell::sync::OnceCell;
-use rtree::{BoundingBox, CoordinateCache};
-use wasm_bindgen::prelude::wasm_bindgen;
+use rtree::{
+    BboxQuery, BoundingBox, BoundingBoxSetResult, CoordinateCache, EntryId, PayloadSize,
+    PointBoundingBox, ValidationError,
+};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{prelude::wasm_bindgen, JsValue};
 
+pub mod error;
+#[cfg(feature = "indexeddb")]
+pub mod indexeddb;
 pub mod rtree;
 
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
-pub static R_TREE: OnceCell<Mutex<CoordinateCache>> = OnceCell::new();
+// Default cache behind the free functions, kept for compatibility with code written before `CacheHandle`.
+// Free functions sharing a name with a `CacheHandle` method delegate to it
+pub static R_TREE: OnceCell<Mutex<CacheHandle>> = OnceCell::new();
+
+/// Data cached from JS, strings from `set_bbox` and friends or JSON values from `set_bbox_value`.
+/// Both share the same cache, ids and limits
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Payload {
+    Text(String),
+    // Serialized JSON, since values can't be read back from the binary snapshot format
+    Json(String),
+}
+
+impl Payload {
+    // Text as is, JSON values serialized
+    fn as_str(&self) -> &str {
+        match self {
+            Payload::Text(text) | Payload::Json(text) => text,
+        }
+    }
+
+    // JS string for text, parsed JS value for JSON
+    fn to_js(&self) -> Result<JsValue, CacheError> {
+        match self {
+            Payload::Text(text) => Ok(text.into()),
+            Payload::Json(json) => js_sys::JSON::parse(json).map_err(|e| {
+                CacheError::new(ErrorKind::Internal, "Stored value is not valid JSON", e)
+            }),
+        }
+    }
+}
+
+impl From<Payload> for String {
+    fn from(payload: Payload) -> Self {
+        match payload {
+            Payload::Text(text) | Payload::Json(text) => text,
+        }
+    }
+}
+
+impl PayloadSize for Payload {
+    fn heap_size(&self) -> usize {
+        match self {
+            Payload::Text(text) | Payload::Json(text) => text.capacity(),
+        }
+    }
+}
 
 #[wasm_bindgen]
+#[derive(Debug, Clone, Copy)]
 pub struct Bbox {
     pub south_west: Coordinate,
     pub south_east: Coordinate,
@@ -35,59 +96,60 @@ impl Bbox {
         }
     }
 
-    pub fn from_osm_bbox(bbox_vec: Vec<f64>) -> Self {
-        let bbox: BoundingBox = bbox_vec.try_into().unwrap();
-        let bbox: Bbox = bbox.into();
-        bbox
+    /// Bbox from an OSM/Nominatim `boundingbox`, `[south, north, west, east]`
+    pub fn from_osm_bbox(bbox_vec: Vec<f64>) -> Result<Bbox, JsValue> {
+        let input = bbox_vec
+            .iter()
+            .map(|value| JsValue::from_f64(*value))
+            .collect::<Array>();
+        let bbox: BoundingBox = bbox_vec.try_into().map_err(|e: ValidationError| {
+            CacheError::new(ErrorKind::InvalidBbox, e.to_string(), input)
+        })?;
+
+        Ok(bbox.into())
     }
 }
 
-// Wasm iterop coordinate
+// Wasm iterop coordinate. Kept as f64, like JS numbers, so nothing is lost before `float_precision` truncation
 #[wasm_bindgen]
 #[derive(Debug, Clone, Copy)]
 pub struct Coordinate {
-    pub x: f32,
-    pub y: f32,
+    pub x: f64,
+    pub y: f64,
 }
 
 #[wasm_bindgen]
 impl Coordinate {
-    pub fn lat(&self) -> f32 {
+    pub fn lat(&self) -> f64 {
         self.y
     }
 
-    pub fn lon(&self) -> f32 {
+    pub fn lon(&self) -> f64 {
         self.x
     }
 
-    pub fn set_lon(&mut self, x: f32) {
+    pub fn set_lon(&mut self, x: f64) {
         self.x = x;
     }
 
-    pub fn set_lat(&mut self, y: f32) {
+    pub fn set_lat(&mut self, y: f64) {
         self.y = y;
     }
 
-    pub fn new(lat: f32, lon: f32) -> Self {
+    pub fn new(lat: f64, lon: f64) -> Self {
         Self { x: lon, y: lat }
     }
 }
 
-impl From<Coordinate> for geo::Coordinate<f64> {
+impl From<Coordinate> for rtree::Coordinate<f64> {
     fn from(c: Coordinate) -> Self {
-        Self {
-            x: c.x as f64,
-            y: c.y as f64,
-        }
+        Self { x: c.x, y: c.y }
     }
 }
 
-impl From<geo::Coordinate<f64>> for Coordinate {
-    fn from(c: geo::Coordinate<f64>) -> Self {
-        Self {
-            x: c.x as f32,
-            y: c.y as f32,
-        }
+impl From<rtree::Coordinate<f64>> for Coordinate {
+    fn from(c: rtree::Coordinate<f64>) -> Self {
+        Self { x: c.x, y: c.y }
     }
 }
 
@@ -114,26 +176,923 @@ impl From<BoundingBox> for Bbox {
 }
 
 #[wasm_bindgen]
-pub fn set_bbox(data: String, bbox: Bbox, reference_point: Option<Coordinate>) {
-    let bbox = BoundingBox::from(bbox);
-    let reference_point = reference_point.map(|c| geo::Coordinate::from(c));
-    let r_tree = R_TREE.get_or_init(|| Mutex::new(CoordinateCache::new()));
-    let mut r_tree = r_tree.lock().unwrap();
+#[derive(Debug, Clone, Copy)]
+pub enum EvictionPolicy {
+    LeastRecentlyHit,
+    Oldest,
+    LargestArea,
+}
+
+impl From<EvictionPolicy> for rtree::EvictionPolicy {
+    fn from(policy: EvictionPolicy) -> Self {
+        match policy {
+            EvictionPolicy::LeastRecentlyHit => Self::LeastRecentlyHit,
+            EvictionPolicy::Oldest => Self::Oldest,
+            EvictionPolicy::LargestArea => Self::LargestArea,
+        }
+    }
+}
+
+/// A cache of its own, independent from the one used by the free functions.
+/// Exported to JS as `CoordinateCache`
+#[wasm_bindgen(js_name = CoordinateCache)]
+#[derive(Debug)]
+pub struct CacheHandle {
+    inner: CoordinateCache<Payload>,
+}
+
+#[wasm_bindgen(js_class = CoordinateCache)]
+impl CacheHandle {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Same as `set_bbox`, on this cache
+    pub fn set(
+        &mut self,
+        data: String,
+        bbox: Bbox,
+        reference_point: Option<Coordinate>,
+        ttl_ms: Option<f64>,
+    ) -> Result<f64, JsValue> {
+        Ok(set_entry(
+            &mut self.inner,
+            Payload::Text(data),
+            bbox,
+            reference_point,
+            ttl_ms,
+        )?)
+    }
+
+    /// Same as `set_bbox_with_max_len`, on this cache
+    pub fn set_with_max_len(
+        &mut self,
+        data: String,
+        bbox: Bbox,
+        reference_point: Coordinate,
+        max_side_len_meters: Option<f64>,
+    ) -> Result<Object, JsValue> {
+        let result = self.inner.set_with_max_len(
+            Payload::Text(data),
+            bounding_box(bbox, self.inner.normalize_corners())?,
+            coordinate(reference_point)?,
+            max_side_len(max_side_len_meters)?,
+        );
+
+        set_result_object(result.map_err(CacheError::from)?)
+    }
+
+    pub fn get(&mut self, coordinate: Coordinate) -> Result<Option<String>, JsValue> {
+        let value = self.inner.get(self::coordinate(coordinate)?);
+        Ok(value.map_err(CacheError::from)?.map(String::from))
+    }
+
+    /// Same as `set_normalize_corners`, on this cache
+    pub fn set_normalize_corners(&mut self, normalize_corners: bool) {
+        self.inner.set_normalize_corners(normalize_corners);
+    }
+
+    pub fn clear(&mut self) {
+        self.inner.clear();
+    }
+
+    /// Same as `init`, on this cache
+    pub fn configure(&mut self, config: JsValue) -> Result<(), JsValue> {
+        let config = CacheConfig::from_js(&config)?;
+
+        let r_tree = &mut self.inner;
+
+        if let Some(float_precision) = config.float_precision {
+            r_tree.set_float_precision(float_precision);
+        }
+
+        if let Some(normalize_corners) = config.normalize_corners {
+            r_tree.set_normalize_corners(normalize_corners);
+        }
+
+        if let Some(max_entries) = config.max_entries {
+            r_tree.set_max_entries(max_entries);
+        }
+
+        if let Some(max_memory_bytes) = config.max_memory_bytes {
+            r_tree.set_max_memory_bytes(max_memory_bytes);
+        }
+
+        Ok(())
+    }
+
+    /// Same as `get_many`, on this cache
+    pub fn get_many(&mut self, coordinates: &[f64]) -> Result<Array, JsValue> {
+        let r_tree = &mut self.inner;
+        let ids = get_ids(r_tree, coordinates)?;
+
+        Ok(ids
+            .into_iter()
+            .map(|id| match id.and_then(|id| r_tree.get_by_id(id)) {
+                Some(data) => JsValue::from_str(data.as_str()),
+                None => JsValue::NULL,
+            })
+            .collect())
+    }
+
+    /// Same as `get_many_indexed`, on this cache
+    pub fn get_many_indexed(&mut self, coordinates: &[f64]) -> Result<Object, JsValue> {
+        let r_tree = &mut self.inner;
+        let ids = get_ids(r_tree, coordinates)?;
+
+        let payloads = Array::new();
+        let mut by_id = BTreeMap::new();
+        let mut by_data = HashMap::new();
+        let mut indices = Vec::with_capacity(ids.len());
+        for id in ids {
+            let index = match id {
+                Some(id) => *by_id.entry(id).or_insert_with(|| {
+                    let data = r_tree.get_by_id(id).map_or("", Payload::as_str);
+                    *by_data
+                        .entry(data)
+                        .or_insert_with(|| payloads.push(&JsValue::from_str(data)) as i32 - 1)
+                }),
+                None => -1,
+            };
+            indices.push(index);
+        }
+
+        let object = Object::new();
+        Reflect::set(&object, &"payloads".into(), &payloads)?;
+        Reflect::set(
+            &object,
+            &"indices".into(),
+            &Int32Array::from(indices.as_slice()),
+        )?;
+
+        Ok(object)
+    }
+
+    /// Same as `get_nearest`, on this cache
+    pub fn get_nearest(
+        &mut self,
+        coordinate: Coordinate,
+        max_distance_meters: f64,
+    ) -> Result<Option<String>, JsValue> {
+        let coordinate = self::coordinate(coordinate)?;
+        let max_distance_meters = distance(max_distance_meters)?;
+        let r_tree = &mut self.inner;
+        let value = r_tree.get_nearest(coordinate, max_distance_meters);
+        Ok(value.map_err(CacheError::from)?.map(String::from))
+    }
+
+    /// Same as `query_bbox`, on this cache
+    pub fn query_bbox(&mut self, bbox: Bbox, within: bool) -> Result<Array, JsValue> {
+        let query = if within {
+            BboxQuery::Within
+        } else {
+            BboxQuery::Intersecting
+        };
+        let r_tree = &self.inner;
+        let entries = r_tree.query_bbox(bounding_box(bbox, r_tree.normalize_corners())?, query);
+
+        entries
+            .into_iter()
+            .map(|entry| {
+                let object = Object::new();
+                Reflect::set(&object, &"data".into(), &entry.data.as_str().into())?;
+                Reflect::set(&object, &"bbox".into(), &bbox_value(entry.bbox))?;
+                Ok(JsValue::from(object))
+            })
+            .collect()
+    }
+
+    /// Same as `get_all`, on this cache
+    pub fn get_all(&mut self, coordinate: Coordinate) -> Result<Array, JsValue> {
+        let containing = self.inner.get_all(self::coordinate(coordinate)?);
+
+        containing
+            .into_iter()
+            .map(|entry| {
+                let object = Object::new();
+                Reflect::set(&object, &"data".into(), &entry.data.as_str().into())?;
+                Reflect::set(&object, &"bbox".into(), &bbox_value(entry.bbox))?;
+                Reflect::set(&object, &"area".into(), &entry.area_meters.into())?;
+                Reflect::set(
+                    &object,
+                    &"distance".into(),
+                    &entry.center_distance_meters.into(),
+                )?;
+                Ok(JsValue::from(object))
+            })
+            .collect()
+    }
+
+    /// Same as `nearest_k`, on this cache
+    pub fn nearest_k(&mut self, coordinate: Coordinate, k: usize) -> Result<Array, JsValue> {
+        let nearest = self.inner.nearest_k(self::coordinate(coordinate)?, k);
+
+        nearest
+            .into_iter()
+            .map(|entry| {
+                let object = Object::new();
+                Reflect::set(&object, &"data".into(), &entry.data.as_str().into())?;
+                Reflect::set(&object, &"distance".into(), &entry.distance_meters.into())?;
+                Reflect::set(&object, &"bbox".into(), &bbox_value(entry.bbox))?;
+                Ok(JsValue::from(object))
+            })
+            .collect()
+    }
+
+    /// Same as `set_bbox_value`, on this cache
+    pub fn set_value(
+        &mut self,
+        data: JsValue,
+        bbox: Bbox,
+        reference_point: Option<Coordinate>,
+        ttl_ms: Option<f64>,
+    ) -> Result<f64, JsValue> {
+        let data = Payload::Json(to_json(&data)?.to_string());
+        let r_tree = &mut self.inner;
+        Ok(set_entry(r_tree, data, bbox, reference_point, ttl_ms)?)
+    }
+
+    /// Same as `get_value`, on this cache
+    pub fn get_value(&mut self, coordinate: Coordinate) -> Result<JsValue, JsValue> {
+        let value = self.inner.get(self::coordinate(coordinate)?);
+
+        match value.map_err(CacheError::from)? {
+            Some(value) => Ok(value.to_js()?),
+            None => Ok(JsValue::UNDEFINED),
+        }
+    }
+
+    /// Same as `set_polygon_geojson`, on this cache
+    pub fn set_polygon_geojson(
+        &mut self,
+        data: String,
+        geometry: JsValue,
+        ttl_ms: Option<f64>,
+    ) -> Result<f64, JsValue> {
+        let polygon = to_json(&geometry).and_then(|json| {
+            rtree::polygon_from_geojson(&json)
+                .map_err(|e| CacheError::new(ErrorKind::InvalidGeometry, e.to_string(), &geometry))
+        })?;
+        let ttl = ttl(ttl_ms)?;
+        let r_tree = &mut self.inner;
+
+        let id = r_tree
+            .set_polygon(Payload::Text(data), polygon, ttl)
+            .map_err(|e| CacheError::new(ErrorKind::InvalidGeometry, e.to_string(), &geometry))?;
+        Ok(id as f64)
+    }
+
+    /// Same as `set_circle`, on this cache
+    pub fn set_circle(
+        &mut self,
+        data: String,
+        center: Coordinate,
+        radius_meters: f64,
+        ttl_ms: Option<f64>,
+    ) -> Result<f64, JsValue> {
+        let center = coordinate(center)?;
+        let radius_meters = distance(radius_meters)?;
+        let ttl = ttl(ttl_ms)?;
+        let r_tree = &mut self.inner;
+        let id = r_tree.set_circle(Payload::Text(data), center, radius_meters, ttl);
+
+        Ok(id.map_err(CacheError::from)? as f64)
+    }
+
+    /// Same as `get_by_id`, on this cache
+    pub fn get_by_id(&mut self, id: f64) -> Result<Option<String>, JsValue> {
+        Ok(self
+            .inner
+            .get_by_id(entry_id(id)?)
+            .cloned()
+            .map(String::from))
+    }
+
+    /// Same as `update_data`, on this cache
+    pub fn update_data(&mut self, id: f64, data: String) -> Result<bool, JsValue> {
+        Ok(self.inner.update_data(entry_id(id)?, Payload::Text(data)))
+    }
+
+    /// Same as `remove`, on this cache
+    pub fn remove(&mut self, id: f64) -> Result<Option<String>, JsValue> {
+        Ok(self.inner.remove(entry_id(id)?).map(String::from))
+    }
+
+    /// Same as `set_memory_budget`, on this cache
+    pub fn set_memory_budget(&mut self, max_bytes: Option<usize>) {
+        self.inner.set_max_memory_bytes(max_bytes);
+    }
+
+    /// Same as `set_eviction_policy`, on this cache
+    pub fn set_eviction_policy(&mut self, policy: EvictionPolicy) {
+        self.inner.set_eviction_policy(policy.into());
+    }
+
+    /// Same as `set_ranking_strategy`, on this cache
+    pub fn set_ranking_strategy(&mut self, strategy: RankingStrategy) {
+        self.inner.set_ranking_strategy(strategy.into());
+    }
+
+    /// Same as `set_priority`, on this cache
+    pub fn set_priority(&mut self, id: f64, priority: i32) -> Result<bool, JsValue> {
+        Ok(self.inner.set_priority(entry_id(id)?, priority))
+    }
+
+    /// Same as `stats`, on this cache
+    pub fn stats(&mut self) -> Result<Object, JsValue> {
+        let stats = self.inner.stats();
+
+        let object = Object::new();
+        let counters = [
+            ("gets", stats.gets),
+            ("hits", stats.hits),
+            ("misses", stats.misses),
+            ("overlapping_hits", stats.overlapping_hits),
+            ("nearest_hits", stats.nearest_hits),
+            ("inserts", stats.inserts),
+            ("truncated_inserts", stats.truncated_inserts),
+            ("evictions", stats.evictions),
+        ];
+        for (name, value) in counters {
+            Reflect::set(&object, &name.into(), &(value as f64).into())?;
+        }
+
+        Ok(object)
+    }
+
+    /// Same as `reset_stats`, on this cache
+    pub fn reset_stats(&mut self) {
+        self.inner.reset_stats();
+    }
+
+    /// Same as `hit_count`, on this cache
+    pub fn hit_count(&mut self, id: f64) -> Result<Option<f64>, JsValue> {
+        let hits = self.inner.hit_count(entry_id(id)?);
+        Ok(hits.map(|hits| hits as f64))
+    }
+
+    /// Same as `memory_usage`, on this cache
+    pub fn memory_usage(&mut self) -> usize {
+        self.inner.memory_usage()
+    }
+
+    /// Same as `set_capacity`, on this cache
+    pub fn set_capacity(&mut self, max_entries: Option<usize>) {
+        self.inner.set_max_entries(max_entries);
+    }
+
+    /// Same as `purge_expired`, on this cache
+    pub fn purge_expired(&mut self) -> usize {
+        self.inner.purge_expired()
+    }
+
+    /// Same as `remove_intersecting`, on this cache
+    pub fn remove_intersecting(&mut self, bbox: Bbox) -> Result<usize, JsValue> {
+        let r_tree = &mut self.inner;
+        let bbox = bounding_box(bbox, r_tree.normalize_corners())?;
+        Ok(r_tree.remove_intersecting(bbox))
+    }
+
+    /// Same as `remove_containing`, on this cache
+    pub fn remove_containing(&mut self, coordinate: Coordinate) -> Result<usize, JsValue> {
+        let coordinate = self::coordinate(coordinate)?;
+        Ok(self.inner.remove_containing(coordinate))
+    }
+
+    /// Same as `export_snapshot`, on this cache
+    pub fn export_snapshot(&mut self) -> Result<Vec<u8>, JsValue> {
+        self.inner.to_bytes().map_err(|e| {
+            CacheError::new(ErrorKind::Internal, e.to_string(), JsValue::UNDEFINED).into()
+        })
+    }
+
+    /// Same as `import_snapshot`, on this cache
+    pub fn import_snapshot(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        let cache = CoordinateCache::from_bytes(bytes).map_err(|e| {
+            let input = js_sys::Uint8Array::from(bytes);
+            CacheError::new(ErrorKind::InvalidSnapshot, e.to_string(), input)
+        })?;
+        self.replace(cache);
+        Ok(())
+    }
+}
+
+impl CacheHandle {
+    // Snapshots don't store the ranking strategy, so the one set on this cache is kept
+    pub(crate) fn replace(&mut self, mut cache: CoordinateCache<Payload>) {
+        cache.set_ranking_strategy(self.inner.ranking_strategy().clone());
+        cache.set_payload_size(Payload::heap_size);
+        self.inner = cache;
+    }
+}
+
+impl Default for CacheHandle {
+    fn default() -> Self {
+        let mut inner = CoordinateCache::new();
+        inner.set_payload_size(Payload::heap_size);
+        Self { inner }
+    }
+}
+
+/// Which entry `get` returns when several contain the coordinate
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy)]
+pub enum RankingStrategy {
+    SmallestArea,
+    CenterDistance,
+    MostRecent,
+    Priority,
+}
+
+impl<T> From<RankingStrategy> for rtree::RankingStrategy<T> {
+    fn from(strategy: RankingStrategy) -> Self {
+        match strategy {
+            RankingStrategy::SmallestArea => Self::SmallestArea,
+            RankingStrategy::CenterDistance => Self::CenterDistance,
+            RankingStrategy::MostRecent => Self::MostRecent,
+            RankingStrategy::Priority => Self::Priority,
+        }
+    }
+}
+
+// Rounding scales coordinates by 10^precision. Longitudes reach 540° once shifted past the antimeridian,
+// and 540·10¹³ still fits the 53 bits of an f64 mantissa while 180·10¹⁴ doesn't
+const MAX_FLOAT_PRECISION: u8 = 13;
+
+// Options read by `init`, `None` leaves the current setting unchanged
+#[derive(Debug, Default)]
+struct CacheConfig {
+    float_precision: Option<u8>,
+    // `Some(None)` removes the limit
+    max_entries: Option<Option<usize>>,
+    max_memory_bytes: Option<Option<usize>>,
+    normalize_corners: Option<bool>,
+}
+
+impl CacheConfig {
+    fn from_js(config: &JsValue) -> Result<Self, CacheError> {
+        if config.is_undefined() {
+            return Ok(Self::default());
+        }
+
+        if !config.is_object() {
+            let message = "Config must be an object";
+            return Err(CacheError::new(ErrorKind::InvalidValue, message, config));
+        }
+
+        let get = |key: &str| {
+            Reflect::get(config, &key.into()).map_err(|e| {
+                CacheError::new(ErrorKind::InvalidValue, format!("Can't read {}", key), e)
+            })
+        };
+        let integer = |key: &str, value: JsValue, max: f64| match value.as_f64() {
+            Some(n) if n.fract() == 0.0 && (0.0..=max).contains(&n) => Ok(n),
+            _ => {
+                let message = format!("{} must be an integer between 0 and {}", key, max);
+                Err(CacheError::new(ErrorKind::InvalidValue, message, value))
+            }
+        };
+        let limit = |key: &str| -> Result<Option<Option<usize>>, CacheError> {
+            let value = get(key)?;
+            if value.is_undefined() {
+                Ok(None)
+            } else if value.is_null() {
+                Ok(Some(None))
+            } else {
+                Ok(Some(Some(integer(key, value, usize::MAX as f64)? as usize)))
+            }
+        };
+
+        let float_precision = get("float_precision")?;
+        let float_precision = if float_precision.is_undefined() {
+            None
+        } else {
+            let max = MAX_FLOAT_PRECISION.into();
+            Some(integer("float_precision", float_precision, max)? as u8)
+        };
+
+        let normalize_corners = get("normalize_corners")?;
+        let normalize_corners = if normalize_corners.is_undefined() {
+            None
+        } else {
+            let message = "normalize_corners must be a boolean";
+            let invalid = || CacheError::new(ErrorKind::InvalidValue, message, &normalize_corners);
+            Some(normalize_corners.as_bool().ok_or_else(invalid)?)
+        };
+
+        Ok(Self {
+            float_precision,
+            max_entries: limit("max_entries")?,
+            max_memory_bytes: limit("max_memory_bytes")?,
+            normalize_corners,
+        })
+    }
+}
+
+/// Configures the global caches, e.g. `init({ float_precision: 6, max_entries: 10000 })`.
+/// Options are `float_precision` (decimal places coordinates are rounded to, 5 by default), `normalize_corners`,
+/// `max_entries` and `max_memory_bytes`. Missing options are left unchanged and `null` removes a limit.
+/// Existing entries are re-snapped when the precision changes
+#[wasm_bindgen]
+pub fn init(config: JsValue) -> Result<(), JsValue> {
+    lock(&R_TREE)?.configure(config)
+}
+
+/// `ttl_ms` is how long (in milliseconds) the entry is valid for, entries without it never expire.
+/// Returns the id of the new entry
+#[wasm_bindgen]
+pub fn set_bbox(
+    data: String,
+    bbox: Bbox,
+    reference_point: Option<Coordinate>,
+    ttl_ms: Option<f64>,
+) -> Result<f64, JsValue> {
+    lock(&R_TREE)?.set(data, bbox, reference_point, ttl_ms)
+}
+
+#[wasm_bindgen]
+pub fn get(coordinate: Coordinate) -> Result<Option<String>, JsValue> {
+    lock(&R_TREE)?.get(coordinate)
+}
+
+/// Looks up every point of `coordinates`, interleaved latitude and longitude pairs like `[lat, lon, lat, lon, ...]`.
+/// Returns the data found for each point, in order, `null` where there's none
+#[wasm_bindgen]
+pub fn get_many(coordinates: &[f64]) -> Result<Array, JsValue> {
+    lock(&R_TREE)?.get_many(coordinates)
+}
+
+/// Same as `get_many`, but returns `{payloads, indices}`. `payloads` holds every distinct data found once,
+/// `indices` is an `Int32Array` with the position in `payloads` for each point, `-1` where there's none
+#[wasm_bindgen]
+pub fn get_many_indexed(coordinates: &[f64]) -> Result<Object, JsValue> {
+    lock(&R_TREE)?.get_many_indexed(coordinates)
+}
+
+/// Same as `get`, but falls back to the closest entry within `max_distance_meters` if none contains the coordinate
+#[wasm_bindgen]
+pub fn get_nearest(
+    coordinate: Coordinate,
+    max_distance_meters: f64,
+) -> Result<Option<String>, JsValue> {
+    lock(&R_TREE)?.get_nearest(coordinate, max_distance_meters)
+}
+
+/// Entries whose bbox intersects `bbox` or, if `within` is true, lies entirely inside it,
+/// as an array of `{data, bbox}` objects
+#[wasm_bindgen]
+pub fn query_bbox(bbox: Bbox, within: bool) -> Result<Array, JsValue> {
+    lock(&R_TREE)?.query_bbox(bbox, within)
+}
+
+/// Every entry containing `coordinate`, best first, as an array of `{data, bbox, area, distance}` objects.
+/// `area` is in square meters and `distance` is from the coordinate to the bbox center, in meters
+#[wasm_bindgen]
+pub fn get_all(coordinate: Coordinate) -> Result<Array, JsValue> {
+    lock(&R_TREE)?.get_all(coordinate)
+}
+
+/// Up to `k` entries closest to `coordinate`, closest first, as an array of `{data, distance, bbox}` objects.
+/// `distance` is in meters, zero for entries containing the coordinate
+#[wasm_bindgen]
+pub fn nearest_k(coordinate: Coordinate, k: usize) -> Result<Array, JsValue> {
+    lock(&R_TREE)?.nearest_k(coordinate, k)
+}
+
+/// Same as `set_bbox`, but stores any JSON compatible value (e.g. a parsed Nominatim address), read back with `get_value`
+#[wasm_bindgen]
+pub fn set_bbox_value(
+    data: JsValue,
+    bbox: Bbox,
+    reference_point: Option<Coordinate>,
+    ttl_ms: Option<f64>,
+) -> Result<f64, JsValue> {
+    lock(&R_TREE)?.set_value(data, bbox, reference_point, ttl_ms)
+}
+
+/// Data of the entry containing `coordinate`, a plain JS object for values set by `set_bbox_value`
+/// and a string for the others. `undefined` if there's none
+#[wasm_bindgen]
+pub fn get_value(coordinate: Coordinate) -> Result<JsValue, JsValue> {
+    lock(&R_TREE)?.get_value(coordinate)
+}
+
+/// Caches `data` for coordinates inside a GeoJSON `Polygon` or `MultiPolygon` geometry, like Nominatim's `polygon_geojson`.
+/// Returns the id of the new entry
+#[wasm_bindgen]
+pub fn set_polygon_geojson(
+    data: String,
+    geometry: JsValue,
+    ttl_ms: Option<f64>,
+) -> Result<f64, JsValue> {
+    lock(&R_TREE)?.set_polygon_geojson(data, geometry, ttl_ms)
+}
+
+/// Caches `data` for coordinates within `radius_meters` of `center`, e.g. a GPS fix and its accuracy.
+/// Returns the id of the new entry
+#[wasm_bindgen]
+pub fn set_circle(
+    data: String,
+    center: Coordinate,
+    radius_meters: f64,
+    ttl_ms: Option<f64>,
+) -> Result<f64, JsValue> {
+    lock(&R_TREE)?.set_circle(data, center, radius_meters, ttl_ms)
+}
+
+// Entry ids cross the boundary as plain JS numbers, they stay exact up to 2^53
+#[wasm_bindgen]
+pub fn get_by_id(id: f64) -> Result<Option<String>, JsValue> {
+    lock(&R_TREE)?.get_by_id(id)
+}
+
+/// Replaces the data of an entry, returns false if there's no entry with this id
+#[wasm_bindgen]
+pub fn update_data(id: f64, data: String) -> Result<bool, JsValue> {
+    lock(&R_TREE)?.update_data(id, data)
+}
+
+/// Removes an entry, returning its data
+#[wasm_bindgen]
+pub fn remove(id: f64) -> Result<Option<String>, JsValue> {
+    lock(&R_TREE)?.remove(id)
+}
+
+/// Swaps the sides of bounding boxes whose south side is north of their north side instead of
+/// rejecting them
+#[wasm_bindgen]
+pub fn set_normalize_corners(normalize_corners: bool) -> Result<(), JsValue> {
+    lock(&R_TREE)?.set_normalize_corners(normalize_corners);
+    Ok(())
+}
+
+/// Limits the memory used by cached entries to `max_bytes`. Passing `undefined` removes the limit
+#[wasm_bindgen]
+pub fn set_memory_budget(max_bytes: Option<usize>) -> Result<(), JsValue> {
+    lock(&R_TREE)?.set_memory_budget(max_bytes);
+    Ok(())
+}
 
-    r_tree.set(data, bbox, reference_point);
+/// Chooses which entries are dropped first when the cache is over its capacity or memory budget
+#[wasm_bindgen]
+pub fn set_eviction_policy(policy: EvictionPolicy) -> Result<(), JsValue> {
+    lock(&R_TREE)?.set_eviction_policy(policy);
+    Ok(())
+}
+
+/// Chooses which entry `get` returns when several contain the coordinate
+#[wasm_bindgen]
+pub fn set_ranking_strategy(strategy: RankingStrategy) -> Result<(), JsValue> {
+    lock(&R_TREE)?.set_ranking_strategy(strategy);
+    Ok(())
 }
 
+/// Sets the priority used by `RankingStrategy.Priority`, higher wins. Returns false if there's no entry with this id
 #[wasm_bindgen]
-pub fn get(coordinate: Coordinate) -> Option<String> {
-    let r_tree = R_TREE.get_or_init(|| Mutex::new(CoordinateCache::new()));
-    let r_tree = r_tree.lock().unwrap();
-    r_tree.get(coordinate.into())
+pub fn set_priority(id: f64, priority: i32) -> Result<bool, JsValue> {
+    lock(&R_TREE)?.set_priority(id, priority)
 }
 
+/// Cache effectiveness counters as a plain object, e.g. `{gets, hits, misses, overlapping_hits, ...}`
 #[wasm_bindgen]
-pub fn clear() {
-    let r_tree = R_TREE.get_or_init(|| Mutex::new(CoordinateCache::new()));
-    r_tree.lock().unwrap().clear();
+pub fn stats() -> Result<Object, JsValue> {
+    lock(&R_TREE)?.stats()
+}
+
+/// Zeroes the `stats` counters and every entry hit count
+#[wasm_bindgen]
+pub fn reset_stats() -> Result<(), JsValue> {
+    lock(&R_TREE)?.reset_stats();
+    Ok(())
+}
+
+/// Times an entry was found containing the coordinate by `get` or `get_nearest`, `undefined` if there's no entry with this id
+#[wasm_bindgen]
+pub fn hit_count(id: f64) -> Result<Option<f64>, JsValue> {
+    lock(&R_TREE)?.hit_count(id)
+}
+
+/// Estimated bytes used by cached entries
+#[wasm_bindgen]
+pub fn memory_usage() -> Result<usize, JsValue> {
+    Ok(lock(&R_TREE)?.memory_usage())
+}
+
+/// Limits the cache to `max_entries`, evicting the least recently hit entries when it's full.
+/// Passing `undefined` removes the limit
+#[wasm_bindgen]
+pub fn set_capacity(max_entries: Option<usize>) -> Result<(), JsValue> {
+    lock(&R_TREE)?.set_capacity(max_entries);
+    Ok(())
+}
+
+/// Removes expired entries from the cache, returning how many were removed
+#[wasm_bindgen]
+pub fn purge_expired() -> Result<usize, JsValue> {
+    Ok(lock(&R_TREE)?.purge_expired())
+}
+
+/// Removes every entry whose bounding box intersects `bbox`, returning how many were removed
+#[wasm_bindgen]
+pub fn remove_intersecting(bbox: Bbox) -> Result<usize, JsValue> {
+    lock(&R_TREE)?.remove_intersecting(bbox)
+}
+
+/// Removes every entry containing `coordinate`, returning how many were removed
+#[wasm_bindgen]
+pub fn remove_containing(coordinate: Coordinate) -> Result<usize, JsValue> {
+    lock(&R_TREE)?.remove_containing(coordinate)
+}
+
+/// Binary snapshot of the whole cache, to be stored and later passed to `import_snapshot`
+#[wasm_bindgen]
+pub fn export_snapshot() -> Result<Vec<u8>, JsValue> {
+    lock(&R_TREE)?.export_snapshot()
+}
+
+/// Replaces the cache with the contents of a snapshot created by `export_snapshot`
+#[wasm_bindgen]
+pub fn import_snapshot(bytes: &[u8]) -> Result<(), JsValue> {
+    lock(&R_TREE)?.import_snapshot(bytes)
+}
+
+#[wasm_bindgen]
+pub fn clear() -> Result<(), JsValue> {
+    lock(&R_TREE)?.clear();
+    Ok(())
+}
+
+/// Same as `set_bbox`, but shrinks the bbox around `reference_point` if a side is longer than `max_side_len_meters`.
+/// Returns an object with the new entry `id`, `truncated`, `is_missing_reference_point` and either
+/// `bbox`, `area_meters`, `width` and `height` or their `old_`/`new_` prefixed pairs if the bbox was truncated
+#[wasm_bindgen]
+pub fn set_bbox_with_max_len(
+    data: String,
+    bbox: Bbox,
+    reference_point: Coordinate,
+    max_side_len_meters: Option<f64>,
+) -> Result<Object, JsValue> {
+    lock(&R_TREE)?.set_with_max_len(data, bbox, reference_point, max_side_len_meters)
+}
+
+// Locks one of the global caches, creating it on first use
+pub(crate) fn lock<C: Default>(
+    cache: &'static OnceCell<Mutex<C>>,
+) -> Result<MutexGuard<'static, C>, CacheError> {
+    cache
+        .get_or_init(|| Mutex::new(C::default()))
+        .lock()
+        .map_err(|_| {
+            let message = "Cache is unusable after a previous panic";
+            CacheError::new(ErrorKind::Internal, message, JsValue::UNDEFINED)
+        })
+}
+
+// Shared by `CacheHandle::set` and `set_bbox_value`
+fn set_entry<T: Clone>(
+    cache: &mut CoordinateCache<T>,
+    data: T,
+    bbox: Bbox,
+    reference_point: Option<Coordinate>,
+    ttl_ms: Option<f64>,
+) -> Result<f64, CacheError> {
+    let bbox = bounding_box(bbox, cache.normalize_corners())?;
+    let reference_point = reference_point.map(coordinate).transpose()?;
+    let ttl = ttl(ttl_ms)?;
+
+    Ok(cache.set_with_ttl(data, bbox, reference_point, ttl)?.id as f64)
+}
+
+// Non-finite coordinates would corrupt the R-tree, so they're rejected before reaching it
+fn coordinate(coordinate: Coordinate) -> Result<rtree::Coordinate<f64>, CacheError> {
+    rtree::validate_coordinate(coordinate.into())
+        .map_err(|e| CacheError::new(ErrorKind::InvalidCoordinate, e.to_string(), coordinate))
+}
+
+// Entries found for interleaved latitude and longitude pairs, every pair is validated before any lookup
+fn get_ids(
+    r_tree: &mut CoordinateCache<Payload>,
+    coordinates: &[f64],
+) -> Result<Vec<Option<EntryId>>, CacheError> {
+    if !coordinates.len().is_multiple_of(2) {
+        let message = "Coordinates must be latitude and longitude pairs";
+        let input = js_sys::Float64Array::from(coordinates);
+        return Err(CacheError::new(ErrorKind::InvalidValue, message, input));
+    }
+
+    let coordinates = coordinates
+        .chunks_exact(2)
+        .enumerate()
+        .map(|(i, pair)| {
+            let coordinate = Coordinate::new(pair[0], pair[1]);
+            self::coordinate(coordinate).map_err(|e| CacheError {
+                message: format!("Coordinate {}: {}", i, e.message),
+                ..e
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(r_tree.get_ids(coordinates)?)
+}
+
+// Ids are integers, casting anything else would silently pick entry 0 or a truncated id
+fn entry_id(id: f64) -> Result<EntryId, CacheError> {
+    const MAX_SAFE_ID: f64 = (1u64 << 53) as f64;
+
+    if id.is_finite() && id.fract() == 0.0 && (0.0..=MAX_SAFE_ID).contains(&id) {
+        Ok(id as EntryId)
+    } else {
+        let message = "Entry id must be a non-negative integer";
+        Err(CacheError::new(ErrorKind::InvalidValue, message, id))
+    }
+}
+
+fn ttl(ms: Option<f64>) -> Result<Option<Duration>, CacheError> {
+    match ms {
+        Some(ms) if !(ms.is_finite() && ms >= 0.0) => {
+            let message = "TTL must be a finite, non-negative number of milliseconds";
+            Err(CacheError::new(ErrorKind::InvalidValue, message, ms))
+        }
+        _ => Ok(ms.map(|ms| Duration::from_millis(ms as u64))),
+    }
+}
+
+fn distance(meters: f64) -> Result<f64, CacheError> {
+    if !(meters.is_finite() && meters >= 0.0) {
+        let message = "Distance must be a finite, non-negative number of meters";
+        return Err(CacheError::new(ErrorKind::InvalidValue, message, meters));
+    }
+
+    Ok(meters)
+}
+
+fn max_side_len(meters: Option<f64>) -> Result<Option<f64>, CacheError> {
+    match meters {
+        Some(meters) if !(meters.is_finite() && meters > 0.0) => {
+            let message = "Max side length must be a finite, positive number of meters";
+            Err(CacheError::new(ErrorKind::InvalidValue, message, meters))
+        }
+        _ => Ok(meters),
+    }
+}
+
+// Swaps upside down corners if the cache is set to `normalize`, like the cache itself would
+fn bounding_box(bbox: Bbox, normalize: bool) -> Result<BoundingBox, CacheError> {
+    rtree::validate_bounding_box(bbox.into(), normalize)
+        .map_err(|e| CacheError::new(ErrorKind::InvalidBbox, e.to_string(), bbox))
+}
+
+fn set_result_object(result: BoundingBoxSetResult) -> Result<Object, JsValue> {
+    let object = Object::new();
+    let set = |key: &str, value: JsValue| Reflect::set(&object, &key.into(), &value);
+
+    match result {
+        BoundingBoxSetResult::SetNotChanged(result) => {
+            set("id", (result.id as f64).into())?;
+            set("truncated", false.into())?;
+            set("bbox", bbox_value(result.bbox))?;
+            set("area_meters", result.area_meters.into())?;
+            set("width", result.width.into())?;
+            set("height", result.height.into())?;
+            set(
+                "is_missing_reference_point",
+                result.is_missing_reference_point.into(),
+            )?;
+        }
+        BoundingBoxSetResult::SetTruncated(result) => {
+            set("id", (result.id as f64).into())?;
+            set("truncated", true.into())?;
+            set("old_bbox", bbox_value(result.old_bbox))?;
+            set("new_bbox", bbox_value(result.new_bbox))?;
+            set("old_area_meters", result.old_area_meters.into())?;
+            set("new_area_meters", result.new_area_meters.into())?;
+            set("old_width", result.old_width.into())?;
+            set("new_width", result.new_width.into())?;
+            set("old_height", result.old_height.into())?;
+            set("new_height", result.new_height.into())?;
+            set(
+                "is_missing_reference_point",
+                result.is_missing_reference_point.into(),
+            )?;
+        }
+    }
+
+    Ok(object)
+}
+
+fn bbox_value(bbox: PointBoundingBox) -> JsValue {
+    Bbox::from(BoundingBox::from(bbox)).into()
+}
+
+fn to_json(value: &JsValue) -> Result<serde_json::Value, CacheError> {
+    let invalid = |message: String| CacheError::new(ErrorKind::InvalidValue, message, value);
+    let json = js_sys::JSON::stringify(value)
+        .ok()
+        .and_then(|json| json.as_string())
+        .ok_or_else(|| invalid("Value is not JSON serializable".to_string()))?;
+
+    serde_json::from_str(&json).map_err(|e| invalid(e.to_string()))
 }
 
 #[wasm_bindgen]
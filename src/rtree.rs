@@ -1,21 +1,186 @@
-use std::convert::TryFrom;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    sync::Arc,
+    time::Duration,
+};
 
+use bincode::Options;
 use geo::{
-    prelude::{ClosestPoint, Contains, HaversineDestination, HaversineDistance},
-    Coordinate, Line, Point, Rect,
+    prelude::{
+        BoundingRect, ClosestPoint, Contains, HaversineDestination, HaversineDistance, MapCoords,
+    },
+    Closest, Line, LineString, MultiPolygon, Point, Polygon, Rect,
+};
+use rstar::{
+    primitives::{GeomWithData, Rectangle},
+    AABB,
 };
-use rstar::primitives::{GeomWithData, Rectangle};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 #[repr(transparent)]
-#[derive(Debug)]
-pub struct Place(pub PlaceWithAddress);
-type PlaceWithAddress = GeomWithData<Rectangle<(f64, f64)>, String>;
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Place<T = String>(pub PlaceWithAddress<T>);
+type PlaceWithAddress<T> = GeomWithData<Rectangle<(f64, f64)>, Address<T>>;
+
+// geo 0.18 only re-exports the `Coordinate` alias, which newer geo-types deprecates
+#[allow(deprecated)]
+pub type Coordinate<T = f64> = geo::Coordinate<T>;
+
+/// Stable handle to a cache entry, never reused by the same cache.
+/// Ids only ever grow, so they order entries by insertion even when set in the same millisecond
+pub type EntryId = u64;
+// What actually goes into the R-tree, the entry itself lives in `CoordinateCache::entries`
+type IndexedPlace = GeomWithData<Rectangle<(f64, f64)>, EntryId>;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Address<T = String> {
+    pub data: T,
+    // Milliseconds since the unix epoch
+    pub inserted_at: u64,
+    pub ttl: Option<Duration>,
+    // Value of the cache clock the last time this entry was inserted or returned by `get`
+    pub last_hit: u64,
+    pub shape: Shape,
+    // Used by `RankingStrategy::Priority`, higher wins
+    pub priority: i32,
+    // Times this entry was found containing the coordinate by `get` or `get_nearest`, since it was inserted or stats were reset
+    pub hits: u64,
+}
+
+/// Exact area covered by an entry. Only its envelope goes into the R-tree,
+/// candidates found there are checked against the shape itself
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Shape {
+    Rectangle,
+    // A plain polygon is kept as a single part
+    Polygon(MultiPolygon<f64>),
+    Circle {
+        center: Coordinate<f64>,
+        radius_meters: f64,
+    },
+}
 
+/// R-tree backed cache of `T` payloads, looked up by coordinates inside their bounding box
 #[derive(Debug)]
-pub struct CoordinateCache {
-    inner: rstar::RTree<PlaceWithAddress>,
+pub struct CoordinateCache<T = String> {
+    inner: rstar::RTree<IndexedPlace>,
+    entries: BTreeMap<EntryId, Place<T>>,
+    // `(eviction_key, id)` of every entry, the first one is evicted first
+    eviction_order: BTreeSet<(u64, EntryId)>,
+    // Inserts into a full cache left until it scans for expired entries again
+    inserts_until_purge: usize,
+    next_id: EntryId,
     float_precision: u8,
+    // Swap the sides of bounding boxes given upside down instead of rejecting them
+    normalize_corners: bool,
+    max_entries: Option<usize>,
+    max_memory_bytes: Option<usize>,
+    memory_usage: usize,
+    eviction_policy: EvictionPolicy,
+    ranking_strategy: RankingStrategy<T>,
+    // Logical clock used to track entry recency
+    clock: u64,
+    // Only recorded after `track_changes` is called
+    changes: Option<ChangeLog>,
+    stats: CacheStats,
+    // Heap bytes owned by a payload, on top of its inline size
+    payload_size: fn(&T) -> usize,
+}
+
+/// Heap bytes owned by a payload, on top of its inline size.
+/// Passed to `CoordinateCache::set_payload_size` as e.g. `String::heap_size`
+pub trait PayloadSize {
+    fn heap_size(&self) -> usize;
+}
+
+impl PayloadSize for String {
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+// Rough estimate, map node overhead isn't accounted for
+impl PayloadSize for serde_json::Value {
+    fn heap_size(&self) -> usize {
+        match self {
+            serde_json::Value::String(s) => s.capacity(),
+            serde_json::Value::Array(values) => {
+                values.capacity() * std::mem::size_of::<serde_json::Value>()
+                    + values.iter().map(PayloadSize::heap_size).sum::<usize>()
+            }
+            serde_json::Value::Object(map) => map
+                .iter()
+                .map(|(key, value)| {
+                    key.capacity() + std::mem::size_of::<serde_json::Value>() + value.heap_size()
+                })
+                .sum(),
+            _ => 0,
+        }
+    }
+}
+
+// Entries inserted, updated or removed since the last `take_changes`
+#[derive(Debug, Default)]
+struct ChangeLog {
+    cleared: bool,
+    upserted: BTreeSet<EntryId>,
+    removed: BTreeSet<EntryId>,
+}
+
+/// Changes to be applied to an external copy of the cache to bring it up to date.
+/// Entries are encoded individually, in the same format used by snapshots
+#[derive(Debug)]
+pub struct CacheChanges {
+    // Every previously stored entry must be dropped before applying the rest
+    pub cleared: bool,
+    // Cache configuration and counters, always present
+    pub metadata: Vec<u8>,
+    pub upserted: Vec<(EntryId, Vec<u8>)>,
+    pub removed: Vec<EntryId>,
+}
+
+/// Scores an entry containing the coordinate passed last, lower scores are returned first by `get`
+pub type RankingFn<T> =
+    Arc<dyn Fn(&Address<T>, &Rectangle<(f64, f64)>, Coordinate<f64>) -> f64 + Send + Sync>;
+
+/// Which entry `get` returns when several contain the coordinate
+#[derive(Clone, Default)]
+pub enum RankingStrategy<T = String> {
+    SmallestArea,
+    // Closest bbox center to the coordinate
+    #[default]
+    CenterDistance,
+    // Most recently inserted
+    MostRecent,
+    // Highest `Address::priority`
+    Priority,
+    Custom(RankingFn<T>),
+}
+
+/// Counters since the cache was created or `reset_stats` was last called
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub gets: u64,
+    pub hits: u64,
+    pub misses: u64,
+    // Hits where several entries contained the coordinate and one had to be picked
+    pub overlapping_hits: u64,
+    // Misses answered by the closest entry in `get_nearest`, also counted in `misses`
+    pub nearest_hits: u64,
+    pub inserts: u64,
+    // Inserts whose bbox was shrunk by `set_with_max_len`
+    pub truncated_inserts: u64,
+    // Entries dropped to respect the capacity or memory budget, expired entries are not counted
+    pub evictions: u64,
+}
+
+/// Which entry is dropped first when the cache is over its capacity or memory budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EvictionPolicy {
+    LeastRecentlyHit,
+    Oldest,
+    LargestArea,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
@@ -38,6 +203,7 @@ pub struct PointBoundingBox {
 
 #[derive(Debug)]
 pub struct SetNotChanged {
+    pub id: EntryId,
     pub area_meters: f64,
     pub bbox: PointBoundingBox,
     pub width: f64,
@@ -47,6 +213,7 @@ pub struct SetNotChanged {
 
 #[derive(Debug)]
 pub struct SetTruncated {
+    pub id: EntryId,
     pub old_area_meters: f64,
     pub new_area_meters: f64,
     pub old_bbox: PointBoundingBox,
@@ -64,17 +231,179 @@ pub enum BoundingBoxSetResult {
     SetTruncated(SetTruncated),
 }
 
+impl BoundingBoxSetResult {
+    pub fn id(&self) -> EntryId {
+        match self {
+            BoundingBoxSetResult::SetNotChanged(result) => result.id,
+            BoundingBoxSetResult::SetTruncated(result) => result.id,
+        }
+    }
+}
+
+/// An entry found by `nearest_k`
+#[derive(Debug, Clone)]
+pub struct NearestEntry<T = String> {
+    pub id: EntryId,
+    pub data: T,
+    // Haversine distance to the entry edge, zero if it contains the coordinate
+    pub distance_meters: f64,
+    pub bbox: PointBoundingBox,
+}
+
+/// Which entries `query_bbox` returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BboxQuery {
+    Intersecting,
+    // Entries whose bbox is entirely inside the queried one
+    Within,
+}
+
+/// An entry found by `query_bbox`
+#[derive(Debug, Clone)]
+pub struct QueriedEntry<T = String> {
+    pub id: EntryId,
+    pub data: T,
+    pub bbox: PointBoundingBox,
+}
+
+/// An entry found by `get_all`
+#[derive(Debug, Clone)]
+pub struct ContainingEntry<T = String> {
+    pub id: EntryId,
+    pub data: T,
+    pub bbox: PointBoundingBox,
+    pub area_meters: f64,
+    // Haversine distance from the coordinate to the bbox center
+    pub center_distance_meters: f64,
+}
+
+/// Why a coordinate or bounding box was rejected
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    // OSM bounding boxes need south, north, west and east values
+    MissingValues(Vec<f64>),
+    NonFinite(Coordinate<f64>),
+    LatitudeOutOfRange(f64),
+    LongitudeOutOfRange(f64),
+    // Corners don't share latitudes and longitudes like the corners of a rectangle do
+    NotAxisAligned(BoundingBox),
+    // South side north of the north side, accepted when the cache normalizes corners
+    SwappedCorners(BoundingBox),
+    // Length in meters that's negative, zero where that makes no sense, or not finite
+    InvalidDistance(f64),
+    // Polygon without any coordinates
+    EmptyPolygon,
+    // Estimated bytes of an entry that wouldn't fit even in an empty cache, given its capacity and memory budget
+    ExceedsLimits(usize),
+}
+
+impl ValidationError {
+    /// Whether a single coordinate, rather than the shape of a bounding box, is at fault
+    pub fn is_coordinate_error(&self) -> bool {
+        matches!(
+            self,
+            ValidationError::NonFinite(_)
+                | ValidationError::LatitudeOutOfRange(_)
+                | ValidationError::LongitudeOutOfRange(_)
+        )
+    }
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationError::MissingValues(values) => write!(
+                f,
+                "Expected [south, north, west, east] bounding box, got {:?}",
+                values
+            ),
+            ValidationError::NonFinite(c) => {
+                write!(f, "Coordinate ({}, {}) is not finite", c.y, c.x)
+            }
+            ValidationError::LatitudeOutOfRange(lat) => {
+                write!(f, "Latitude {} is outside [-90, 90]", lat)
+            }
+            ValidationError::LongitudeOutOfRange(lon) => {
+                write!(f, "Longitude {} is outside [-180, 180]", lon)
+            }
+            ValidationError::NotAxisAligned(bbox) => {
+                write!(f, "Bounding box corners are not axis aligned {:?}", bbox)
+            }
+            ValidationError::SwappedCorners(bbox) => {
+                write!(
+                    f,
+                    "Bounding box south side is north of its north side {:?}",
+                    bbox
+                )
+            }
+            ValidationError::InvalidDistance(meters) => {
+                write!(f, "{} is not a valid distance in meters", meters)
+            }
+            ValidationError::EmptyPolygon => write!(f, "Polygon has no coordinates"),
+            ValidationError::ExceedsLimits(bytes) => write!(
+                f,
+                "Entry of {} bytes can't fit within the cache capacity and memory budget",
+                bytes
+            ),
+        }
+    }
+}
+impl std::error::Error for ValidationError {}
+
+#[derive(Debug)]
+pub struct GeoJsonConversionError {
+    _geometry: serde_json::Value,
+}
+
+impl std::fmt::Display for GeoJsonConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "GeoJSON conversion error {:?}", self)
+    }
+}
+impl std::error::Error for GeoJsonConversionError {}
+
+// Leading bytes of every snapshot, followed by the format version
+const SNAPSHOT_MAGIC: &[u8; 4] = b"RTCS";
+const SNAPSHOT_VERSION: u8 = 7;
+
+// Everything needed to rebuild a cache, the R-tree itself is rebuilt from the entries.
+// `E` is a reference to the entries when writing and the owned entries when reading
+#[derive(Serialize, Deserialize)]
+struct Snapshot<E> {
+    float_precision: u8,
+    normalize_corners: bool,
+    max_entries: Option<usize>,
+    max_memory_bytes: Option<usize>,
+    eviction_policy: EvictionPolicy,
+    next_id: EntryId,
+    clock: u64,
+    entries: E,
+}
+
 #[derive(Debug)]
-pub struct BoundingBoxConversionError {
-    _bounding_box: Vec<f64>,
+pub enum SnapshotError {
+    InvalidHeader,
+    UnsupportedVersion(u8),
+    Encode(bincode::Error),
+    Decode(bincode::Error),
+    // Entry whose rectangle has non-finite corners
+    InvalidEntry(EntryId),
 }
 
-impl std::fmt::Display for BoundingBoxConversionError {
+impl std::fmt::Display for SnapshotError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Bounding box conversion error {:?}", self)
+        match self {
+            SnapshotError::InvalidHeader => write!(f, "Not a cache snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported snapshot version {}", version)
+            }
+            SnapshotError::Encode(e) => write!(f, "Snapshot encode error {}", e),
+            SnapshotError::Decode(e) => write!(f, "Snapshot decode error {}", e),
+            SnapshotError::InvalidEntry(id) => write!(f, "Invalid snapshot entry {}", id),
+        }
     }
 }
-impl std::error::Error for BoundingBoxConversionError {}
+impl std::error::Error for SnapshotError {}
 
 impl From<BoundingBox> for PointBoundingBox {
     fn from(b: BoundingBox) -> Self {
@@ -87,66 +416,347 @@ impl From<BoundingBox> for PointBoundingBox {
     }
 }
 
-impl CoordinateCache {
-    pub fn new() -> Self {
+impl From<PointBoundingBox> for BoundingBox {
+    fn from(b: PointBoundingBox) -> Self {
         Self {
-            inner: rstar::RTree::default(),
-            float_precision: 5,
+            north_east: b.north_east.into(),
+            north_west: b.north_west.into(),
+            south_east: b.south_east.into(),
+            south_west: b.south_west.into(),
         }
     }
+}
+
+// Entry rectangles crossing the antimeridian come back with their east side west of their west side
+impl From<&Rectangle<(f64, f64)>> for PointBoundingBox {
+    fn from(rect: &Rectangle<(f64, f64)>) -> Self {
+        let (west, south) = rect.lower();
+        let (east, north) = rect.upper();
+
+        normalize_point_bounding_box(Self {
+            south_west: Point::new(west, south),
+            south_east: Point::new(east, south),
+            north_west: Point::new(west, north),
+            north_east: Point::new(east, north),
+        })
+    }
+}
+
+impl<T: Clone> CoordinateCache<T> {
+    pub fn new() -> Self {
+        Self::new_with_precision(5)
+    }
 
+    /// Removes every entry, ids are not reused afterwards
     pub fn clear(&mut self) {
-        *self = Self {
-            inner: rstar::RTree::default(),
-            float_precision: self.float_precision,
-        };
+        self.inner = rstar::RTree::default();
+        self.entries.clear();
+        self.eviction_order.clear();
+        self.memory_usage = 0;
+
+        if let Some(changes) = self.changes.as_mut() {
+            *changes = ChangeLog {
+                cleared: true,
+                ..ChangeLog::default()
+            };
+        }
     }
 
     pub fn new_with_precision(float_precision: u8) -> Self {
         Self {
             inner: rstar::RTree::default(),
+            entries: BTreeMap::new(),
+            eviction_order: BTreeSet::new(),
+            inserts_until_purge: 0,
+            next_id: 0,
             float_precision,
+            normalize_corners: false,
+            max_entries: None,
+            max_memory_bytes: None,
+            memory_usage: 0,
+            eviction_policy: EvictionPolicy::LeastRecentlyHit,
+            ranking_strategy: RankingStrategy::default(),
+            clock: 0,
+            changes: None,
+            stats: CacheStats::default(),
+            payload_size: inline_size_only,
+        }
+    }
+
+    /// Starts recording changes for `take_changes`. The first batch after this call rewrites every entry
+    pub fn track_changes(&mut self) {
+        self.changes = Some(ChangeLog {
+            cleared: true,
+            upserted: self.entries.keys().copied().collect(),
+            removed: BTreeSet::new(),
+        });
+    }
+
+    pub fn is_tracking_changes(&self) -> bool {
+        self.changes.is_some()
+    }
+
+    /// Sets how the heap memory owned by a payload is measured for `memory_usage` and the memory budget.
+    /// Only the inline size of payloads counts by default
+    pub fn set_payload_size(&mut self, payload_size: fn(&T) -> usize) {
+        self.payload_size = payload_size;
+        self.memory_usage = self.entries.values().map(|p| self.entry_size(&p.0)).sum();
+        self.shrink_to_limits();
+    }
+
+    /// Creates a cache holding at most `max_entries`, evicting the least recently hit entries when full
+    pub fn new_with_capacity(max_entries: usize) -> Self {
+        let mut cache = Self::new();
+        cache.max_entries = Some(max_entries);
+        cache
+    }
+
+    /// Decimal places coordinates are rounded to
+    pub fn float_precision(&self) -> u8 {
+        self.float_precision
+    }
+
+    /// Changes the decimal places coordinates are rounded to, re-snapping existing entries to the new precision
+    pub fn set_float_precision(&mut self, float_precision: u8) {
+        if float_precision == self.float_precision {
+            return;
+        }
+
+        self.float_precision = float_precision;
+        let entries = std::mem::take(&mut self.entries);
+        self.entries = entries
+            .into_iter()
+            .map(|(id, place)| (id, place.snapped(float_precision)))
+            .collect();
+        self.inner = index_entries(&self.entries);
+        self.memory_usage = self.entries.values().map(|p| self.entry_size(&p.0)).sum();
+        // Snapping changes areas
+        self.index_eviction_order();
+
+        if let Some(changes) = self.changes.as_mut() {
+            changes.upserted.extend(self.entries.keys().copied());
+        }
+
+        self.shrink_to_limits();
+    }
+
+    pub fn max_entries(&self) -> Option<usize> {
+        self.max_entries
+    }
+
+    /// Changes the cache capacity, evicting entries if it's already over the new limit
+    pub fn set_max_entries(&mut self, max_entries: Option<usize>) {
+        self.max_entries = max_entries;
+        self.shrink_to_limits();
+    }
+
+    /// Creates a cache whose entries (rectangle plus payload) take at most `max_memory_bytes`
+    pub fn new_with_memory_budget(
+        max_memory_bytes: usize,
+        eviction_policy: EvictionPolicy,
+    ) -> Self {
+        let mut cache = Self::new();
+        cache.max_memory_bytes = Some(max_memory_bytes);
+        cache.eviction_policy = eviction_policy;
+        cache
+    }
+
+    pub fn max_memory_bytes(&self) -> Option<usize> {
+        self.max_memory_bytes
+    }
+
+    /// Changes the memory budget, evicting entries if it's already over the new limit
+    pub fn set_max_memory_bytes(&mut self, max_memory_bytes: Option<usize>) {
+        self.max_memory_bytes = max_memory_bytes;
+        self.shrink_to_limits();
+    }
+
+    pub fn eviction_policy(&self) -> EvictionPolicy {
+        self.eviction_policy
+    }
+
+    pub fn set_eviction_policy(&mut self, eviction_policy: EvictionPolicy) {
+        if eviction_policy != self.eviction_policy {
+            self.eviction_policy = eviction_policy;
+            self.index_eviction_order();
         }
     }
 
+    pub fn ranking_strategy(&self) -> &RankingStrategy<T> {
+        &self.ranking_strategy
+    }
+
+    pub fn set_ranking_strategy(&mut self, ranking_strategy: RankingStrategy<T>) {
+        self.ranking_strategy = ranking_strategy;
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Zeroes every counter, including the hit count of each entry
+    pub fn reset_stats(&mut self) {
+        self.stats = CacheStats::default();
+
+        for Place(place) in self.entries.values_mut() {
+            place.data.hits = 0;
+        }
+    }
+
+    /// Times an entry was found containing the coordinate by `get` or `get_nearest`
+    pub fn hit_count(&self, id: EntryId) -> Option<u64> {
+        self.entries.get(&id).map(|p| p.0.data.hits)
+    }
+
+    /// Estimated bytes used by the cached entries
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Whether bounding boxes with their south side north of their north side are swapped instead of rejected
+    pub fn normalize_corners(&self) -> bool {
+        self.normalize_corners
+    }
+
+    pub fn set_normalize_corners(&mut self, normalize_corners: bool) {
+        self.normalize_corners = normalize_corners;
+    }
+
+    /// Caches `data` for coordinates inside `bbox`. Fails if `bbox` or `reference_point` aren't valid coordinates,
+    /// or if the entry can't fit within the capacity and memory budget even after evicting every other one
     pub fn set(
         &mut self,
-        data: String,
+        data: T,
+        bbox: BoundingBox,
+        reference_point: Option<Coordinate<f64>>,
+    ) -> Result<SetNotChanged, ValidationError> {
+        self.set_with_ttl(data, bbox, reference_point, None)
+    }
+
+    /// Same as `set`, but the entry is ignored by `get` (and dropped by `purge_expired`) once `ttl` has elapsed
+    pub fn set_with_ttl(
+        &mut self,
+        data: T,
         bbox: BoundingBox,
         reference_point: Option<Coordinate<f64>>,
-    ) -> SetNotChanged {
+        ttl: Option<Duration>,
+    ) -> Result<SetNotChanged, ValidationError> {
+        let bbox = validate_bounding_box(bbox, self.normalize_corners)?;
+        let reference_point = reference_point.map(validate_coordinate).transpose()?;
         let bbox = truncate_bounding_box(bbox, self.float_precision);
         let reference_point = reference_point.map(|c| truncate_coordinate(c, self.float_precision));
         let bbox = PointBoundingBox::from(bbox);
-        let place = Place::new(bbox.north_west, bbox.south_east, data);
+        let place = Place::new(bbox.north_west, bbox.south_east, Address::new(data, ttl));
         let width = bbox.north_east.haversine_distance(&bbox.north_west);
         let height = bbox.north_east.haversine_distance(&bbox.south_east);
 
-        let rect = Rect::new(bbox.north_west, bbox.south_east);
+        let unwrapped = unwrap_antimeridian(bbox);
+        let rect = Rect::new(unwrapped.north_west, unwrapped.south_east);
 
         let is_missing_reference_point = reference_point
-            .map(|c| !rect.contains(&Point::from(c)))
+            .map(|c| !rect.contains(&unwrap_point(Point::from(c), &unwrapped)))
             .unwrap_or(false);
 
-        self.inner.insert(place.0);
+        let id = self.insert(place)?;
 
-        SetNotChanged {
+        Ok(SetNotChanged {
+            id,
             area_meters: width * height,
             bbox,
             width,
             height,
             is_missing_reference_point,
+        })
+    }
+
+    /// Caches `data` for coordinates inside `polygon`, a `Polygon` or `MultiPolygon`, holes included.
+    /// Fails if the polygon is empty or any of its coordinates isn't valid
+    pub fn set_polygon(
+        &mut self,
+        data: T,
+        polygon: impl Into<MultiPolygon<f64>>,
+        ttl: Option<Duration>,
+    ) -> Result<EntryId, ValidationError> {
+        let polygon = polygon.into();
+        for coordinate in polygon.iter().flat_map(|part| {
+            part.exterior()
+                .0
+                .iter()
+                .chain(part.interiors().iter().flat_map(|ring| ring.0.iter()))
+        }) {
+            validate_coordinate(*coordinate)?;
+        }
+
+        let polygon = truncate_polygon(&polygon, self.float_precision);
+        let (north_west, south_east) =
+            polygon_envelope(&polygon).ok_or(ValidationError::EmptyPolygon)?;
+
+        let place = Place::new(
+            north_west,
+            south_east,
+            Address {
+                shape: Shape::Polygon(polygon),
+                ..Address::new(data, ttl)
+            },
+        );
+
+        self.insert(place)
+    }
+
+    /// Caches `data` for coordinates within `radius_meters` (haversine distance) of `center`.
+    /// Fails if `center` isn't valid or the radius is negative or not finite
+    pub fn set_circle(
+        &mut self,
+        data: T,
+        center: Coordinate<f64>,
+        radius_meters: f64,
+        ttl: Option<Duration>,
+    ) -> Result<EntryId, ValidationError> {
+        let center = validate_coordinate(center)?;
+        if !(radius_meters.is_finite() && radius_meters >= 0.0) {
+            return Err(ValidationError::InvalidDistance(radius_meters));
         }
+
+        let center = truncate_coordinate(center, self.float_precision);
+        let (north_west, south_east) = circle_envelope(center, radius_meters);
+
+        let place = Place::new(
+            north_west,
+            south_east,
+            Address {
+                shape: Shape::Circle {
+                    center,
+                    radius_meters,
+                },
+                ..Address::new(data, ttl)
+            },
+        );
+
+        self.insert(place)
     }
 
     pub fn set_with_max_len(
         &mut self,
-        data: String,
+        data: T,
         bbox: BoundingBox,
         reference_point: Coordinate<f64>,
         max_side_len_meters: Option<f64>,
-    ) -> BoundingBoxSetResult {
-        let reference_point: Point<f64> = reference_point.into();
+    ) -> Result<BoundingBoxSetResult, ValidationError> {
+        // A negative length would move the west side past the east side, wrapping the box around the globe
+        if let Some(max_len) = max_side_len_meters.filter(|len| !(len.is_finite() && *len > 0.0)) {
+            return Err(ValidationError::InvalidDistance(max_len));
+        }
+
+        let bbox = validate_bounding_box(bbox, self.normalize_corners)?;
+        let reference_point: Point<f64> = validate_coordinate(reference_point)?.into();
         let bbox = truncate_bounding_box(bbox, self.float_precision);
         let reference_point =
             Point::from(truncate_coordinate(reference_point.0, self.float_precision));
@@ -156,19 +766,26 @@ impl CoordinateCache {
         let width = bbox.north_east.haversine_distance(&bbox.north_west);
         let height = bbox.north_east.haversine_distance(&bbox.south_east);
 
-        let rect = Rect::new(bbox.north_west, bbox.south_east);
-        let is_missing_reference_point = !rect.contains(&reference_point);
+        let unwrapped = unwrap_antimeridian(bbox);
+        let rect = Rect::new(unwrapped.north_west, unwrapped.south_east);
+        let is_missing_reference_point = !rect.contains(&unwrap_point(reference_point, &unwrapped));
 
         match max_side_len_meters {
             Some(max_len) if width > max_len || height > max_len => {
                 let new_bbox = Self::fix_rect(bbox, max_len, reference_point, self.float_precision);
-                let place = Place::new(new_bbox.north_west, new_bbox.south_east, data);
-                self.inner.insert(place.0);
+                let place = Place::new(
+                    new_bbox.north_west,
+                    new_bbox.south_east,
+                    Address::new(data, None),
+                );
+                let id = self.insert(place)?;
+                self.stats.truncated_inserts += 1;
 
                 let new_width = new_bbox.north_east.haversine_distance(&new_bbox.north_west);
                 let new_height = new_bbox.north_east.haversine_distance(&new_bbox.south_east);
 
-                BoundingBoxSetResult::SetTruncated(SetTruncated {
+                Ok(BoundingBoxSetResult::SetTruncated(SetTruncated {
+                    id,
                     new_area_meters: new_height * new_width,
                     new_bbox,
                     old_bbox: bbox,
@@ -178,31 +795,217 @@ impl CoordinateCache {
                     old_height: height,
                     old_width: width,
                     is_missing_reference_point,
-                })
+                }))
             }
             _ => {
-                let place = Place::new(bbox.north_west, bbox.south_east, data);
-                self.inner.insert(place.0);
-                BoundingBoxSetResult::SetNotChanged(SetNotChanged {
+                let place = Place::new(bbox.north_west, bbox.south_east, Address::new(data, None));
+                let id = self.insert(place)?;
+                Ok(BoundingBoxSetResult::SetNotChanged(SetNotChanged {
+                    id,
                     area_meters: width * height,
                     bbox,
                     height,
                     width,
                     is_missing_reference_point,
-                })
+                }))
             }
         }
     }
 
+    fn insert(&mut self, place: Place<T>) -> Result<EntryId, ValidationError> {
+        let Place(mut place) = place;
+        let size = self.entry_size(&place);
+
+        // Entry would never fit, even in an empty cache
+        if self.max_entries == Some(0) || self.max_memory_bytes.is_some_and(|max| size > max) {
+            return Err(ValidationError::ExceedsLimits(size));
+        }
+
+        // Non-finite corners would break the R-tree
+        let (lower, upper) = (place.geom().lower(), place.geom().upper());
+        for (x, y) in [lower, upper] {
+            let corner = Coordinate { x, y };
+            if !is_finite_coordinate(corner) {
+                return Err(ValidationError::NonFinite(corner));
+            }
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+
+        // Make room by dropping expired entries first, then by following the eviction policy.
+        // Finding expired entries is a linear scan, so it only runs once every as many inserts as there are entries
+        if !self.fits(1, size) {
+            match self.inserts_until_purge.checked_sub(1) {
+                Some(left) => self.inserts_until_purge = left,
+                None => {
+                    self.purge_expired();
+                }
+            }
+        }
+
+        while !self.fits(1, size) && self.evict() {}
+
+        place.data.last_hit = self.tick();
+        self.memory_usage += size;
+        for rect in indexed_rectangles(place.geom()) {
+            self.inner.insert(IndexedPlace::new(rect, id));
+        }
+        self.eviction_order
+            .insert((eviction_key(self.eviction_policy, id, &place), id));
+        self.entries.insert(id, Place(place));
+        self.stats.inserts += 1;
+
+        if let Some(changes) = self.changes.as_mut() {
+            changes.upserted.insert(id);
+        }
+
+        Ok(id)
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    // Marks an entry as hit at `tick`, keeping the least recently hit order up to date
+    fn mark_hit(&mut self, id: EntryId, tick: u64) -> Option<&mut PlaceWithAddress<T>> {
+        let Place(place) = self.entries.get_mut(&id)?;
+        if self.eviction_policy == EvictionPolicy::LeastRecentlyHit {
+            self.eviction_order.remove(&(place.data.last_hit, id));
+            self.eviction_order.insert((tick, id));
+        }
+
+        place.data.last_hit = tick;
+        Some(place)
+    }
+
+    fn index_eviction_order(&mut self) {
+        self.eviction_order = self
+            .entries
+            .iter()
+            .map(|(id, place)| (eviction_key(self.eviction_policy, *id, &place.0), *id))
+            .collect();
+    }
+
+    // Whether `entries` more entries taking `bytes` can be inserted without going over the limits
+    fn fits(&self, entries: usize, bytes: usize) -> bool {
+        let fits_entries = self
+            .max_entries
+            .is_none_or(|max| self.entries.len() + entries <= max);
+        let fits_memory = self
+            .max_memory_bytes
+            .is_none_or(|max| self.memory_usage + bytes <= max);
+
+        fits_entries && fits_memory
+    }
+
+    fn shrink_to_limits(&mut self) {
+        while !self.fits(0, 0) && self.evict() {}
+    }
+
+    // Returns false when there was nothing to evict
+    fn evict(&mut self) -> bool {
+        let victim = self.eviction_order.first().map(|(_, id)| *id);
+
+        let evicted = victim.is_some_and(|victim| self.remove(victim).is_some());
+        if evicted {
+            self.stats.evictions += 1;
+        }
+
+        evicted
+    }
+
+    /// Returns the data of a live entry
+    pub fn get_by_id(&self, id: EntryId) -> Option<&T> {
+        let now = now_millis();
+        self.entries
+            .get(&id)
+            .filter(|p| !p.0.data.is_expired(now))
+            .map(|p| &p.0.data.data)
+    }
+
+    /// Replaces the data of an entry, keeping its rectangle and expiry. Returns false if there's no such entry
+    pub fn update_data(&mut self, id: EntryId, data: T) -> bool {
+        let place = match self.entries.get_mut(&id) {
+            Some(place) => place,
+            None => return false,
+        };
+
+        let old_size = (self.payload_size)(&place.0.data.data);
+        place.0.data.data = data;
+        let new_size = (self.payload_size)(&place.0.data.data);
+
+        self.memory_usage = self.memory_usage.saturating_sub(old_size) + new_size;
+
+        if let Some(changes) = self.changes.as_mut() {
+            changes.upserted.insert(id);
+        }
+
+        self.shrink_to_limits();
+        true
+    }
+
+    /// Sets the priority used by `RankingStrategy::Priority`, returns false if there's no entry with this id
+    pub fn set_priority(&mut self, id: EntryId, priority: i32) -> bool {
+        let place = match self.entries.get_mut(&id) {
+            Some(place) => place,
+            None => return false,
+        };
+
+        place.0.data.priority = priority;
+
+        if let Some(changes) = self.changes.as_mut() {
+            changes.upserted.insert(id);
+        }
+
+        true
+    }
+
+    /// Removes an entry, returning its data
+    pub fn remove(&mut self, id: EntryId) -> Option<T> {
+        let Place(place) = self.remove_entry(id)?;
+        for rect in indexed_rectangles(place.geom()) {
+            self.inner.remove(&IndexedPlace::new(rect, id));
+        }
+
+        Some(place.data.data)
+    }
+
+    // Removes an entry that's already out of the R-tree
+    fn remove_entry(&mut self, id: EntryId) -> Option<Place<T>> {
+        let place = self.entries.remove(&id)?;
+        self.memory_usage = self.memory_usage.saturating_sub(self.entry_size(&place.0));
+        self.eviction_order
+            .remove(&(eviction_key(self.eviction_policy, id, &place.0), id));
+
+        if let Some(changes) = self.changes.as_mut() {
+            changes.upserted.remove(&id);
+            changes.removed.insert(id);
+        }
+
+        Some(place)
+    }
+
+    // Indexed rectangle, stored entry and the payload heap allocation
+    fn entry_size(&self, place: &PlaceWithAddress<T>) -> usize {
+        std::mem::size_of::<IndexedPlace>()
+            + std::mem::size_of::<(EntryId, PlaceWithAddress<T>)>()
+            + (self.payload_size)(&place.data.data)
+            + place.data.shape.heap_size()
+    }
+
     fn fix_rect(
         bbox: PointBoundingBox,
         max_len_side: f64,
         reference: Point<f64>,
         float_precision: u8,
     ) -> PointBoundingBox {
+        // Work with the east side shifted past 180° if the box crosses the antimeridian
+        let bbox = unwrap_antimeridian(bbox);
         let rect = Rect::new(bbox.north_west, bbox.south_east);
 
-        let mut reference = reference;
+        let mut reference = unwrap_point(reference, &bbox);
         // If reference point is not inside the container, set reference point as the rectangle centroid so if doesn't do anything too weird
         if !rect.contains(&reference) {
             // Set reference point as rectangle center
@@ -271,7 +1074,7 @@ impl CoordinateCache {
         let fixed_northeast_point = Point::new(fixed_east_point.x(), fixed_north_point.y());
         let fixed_southeast_point = Point::new(fixed_east_point.x(), fixed_south_point.y());
 
-        truncate_point_bounding_box(
+        let fixed_bbox = truncate_point_bounding_box(
             PointBoundingBox {
                 south_west: fixed_southwest_point,
                 south_east: fixed_southeast_point,
@@ -279,7 +1082,9 @@ impl CoordinateCache {
                 north_east: fixed_northeast_point,
             },
             float_precision,
-        )
+        );
+
+        normalize_point_bounding_box(fixed_bbox)
     }
 
     fn closest_point(line: &Line<f64>, point: &Point<f64>) -> Point<f64> {
@@ -290,18 +1095,124 @@ impl CoordinateCache {
         }
     }
 
-    pub fn get(&self, coordinate: Coordinate<f64>) -> Option<String> {
+    /// Removes every expired entry from the tree, returning how many were removed
+    pub fn purge_expired(&mut self) -> usize {
+        let now = now_millis();
+        let expired = self
+            .entries
+            .iter()
+            .filter(|(_, p)| p.0.data.is_expired(now))
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+
+        for id in expired.iter() {
+            self.remove(*id);
+        }
+        self.inserts_until_purge = self.entries.len();
+
+        expired.len()
+    }
+
+    /// Removes every entry whose rectangle intersects `bbox`, returning how many were removed
+    pub fn remove_intersecting(&mut self, bbox: BoundingBox) -> usize {
+        let bbox = unwrap_antimeridian(truncate_bounding_box(bbox, self.float_precision).into());
+        let rect = Rectangle::from_corners(bbox.north_west.x_y(), bbox.south_east.x_y());
+
+        indexed_rectangles(&rect)
+            .map(|piece| self.drain_intersecting(AABB::from_corners(piece.lower(), piece.upper())))
+            .sum()
+    }
+
+    /// Removes every entry containing `coordinate`, returning how many were removed.
+    /// Polygons and circles are checked against their exact shape, like in `get`
+    pub fn remove_containing(&mut self, coordinate: Coordinate<f64>) -> usize {
         let coordinate = truncate_coordinate(coordinate, self.float_precision);
-        let mut places_containing_point = self.inner.locate_all_at_point(&coordinate.x_y());
+        let entries = &self.entries;
+        let containing = self
+            .inner
+            .locate_all_at_point(&coordinate.x_y())
+            .map(|indexed| indexed.data)
+            .filter(|id| {
+                entries
+                    .get(id)
+                    .is_some_and(|place| place.0.data.shape.contains(coordinate))
+            })
+            .collect::<BTreeSet<_>>();
+
+        containing
+            .into_iter()
+            .filter(|id| self.remove(*id).is_some())
+            .count()
+    }
+
+    fn drain_intersecting(&mut self, envelope: AABB<(f64, f64)>) -> usize {
+        let removed = self
+            .inner
+            .drain_in_envelope_intersecting(envelope)
+            .map(|indexed| indexed.data)
+            .collect::<BTreeSet<_>>();
+
+        // Entries crossing the antimeridian may still have their other half indexed
+        removed
+            .into_iter()
+            .filter(|id| self.remove(*id).is_some())
+            .count()
+    }
+
+    /// Returns the data of the best entry containing `coordinate`, marking it as recently hit.
+    /// Fails if `coordinate` isn't a valid coordinate
+    pub fn get(&mut self, coordinate: Coordinate<f64>) -> Result<Option<T>, ValidationError> {
+        let coordinate = validate_coordinate(coordinate)?;
+        Ok(self.get_valid(coordinate))
+    }
+
+    /// Same as calling `get` on every coordinate, but returns the ids of the entries found, in order.
+    /// Their data is read with `get_by_id`, so an entry hit many times is only cloned when needed.
+    /// Fails before looking anything up if a coordinate isn't valid
+    pub fn get_ids<I>(&mut self, coordinates: I) -> Result<Vec<Option<EntryId>>, ValidationError>
+    where
+        I: IntoIterator<Item = Coordinate<f64>>,
+    {
+        let coordinates = coordinates
+            .into_iter()
+            .map(validate_coordinate)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(coordinates
+            .into_iter()
+            .map(|coordinate| self.get_valid_id(coordinate))
+            .collect())
+    }
+
+    fn get_valid(&mut self, coordinate: Coordinate<f64>) -> Option<T> {
+        let id = self.get_valid_id(coordinate)?;
+        self.entries.get(&id).map(|p| p.0.data.data.clone())
+    }
+
+    fn get_valid_id(&mut self, coordinate: Coordinate<f64>) -> Option<EntryId> {
+        let coordinate = truncate_coordinate(coordinate, self.float_precision);
+        let now = now_millis();
+        let tick = self.tick();
+        let entries = &self.entries;
+        let ranking_strategy = &self.ranking_strategy;
+        let mut places_containing_point =
+            self.inner
+                .locate_all_at_point(&coordinate.x_y())
+                .filter(|p| {
+                    entries.get(&p.data).is_some_and(|e| {
+                        !e.0.data.is_expired(now) && e.0.data.shape.contains(coordinate)
+                    })
+                });
         let first = places_containing_point.next();
         let second = places_containing_point.next();
+        let overlapping = second.is_some();
 
         // If we have only a single point, return data w/o any extra allocations
-        if second.is_none() {
-            first.map(|f| f.data.clone())
+        let place = if second.is_none() {
+            first
         } else {
             // We have more than a single point
-            let mut places = places_containing_point.collect::<Vec<_>>();
+            let mut places = places_containing_point.by_ref().collect::<Vec<_>>();
 
             if let Some(first) = first {
                 places.push(first);
@@ -311,39 +1222,761 @@ impl CoordinateCache {
                 places.push(second);
             };
 
-            // Sort by distance from point to rectangle center
-            places.sort_by(|a, b| {
-                let rect_a_center: Point<f64> = geo::Rect::new(a.geom().upper(), a.geom().lower())
-                    .center()
-                    .into();
-                let rect_b_center: Point<f64> = geo::Rect::new(b.geom().upper(), b.geom().lower())
-                    .center()
-                    .into();
+            // Rank using the whole entry rather than the indexed half of an antimeridian-crossing one
+            let score = |p: &IndexedPlace| {
+                entries.get(&p.data).map_or(f64::INFINITY, |Place(place)| {
+                    ranking_strategy.score(p.data, &place.data, place.geom(), coordinate)
+                })
+            };
+            places.sort_by(|a, b| score(a).total_cmp(&score(b)));
 
-                rect_a_center
-                    .haversine_distance(&Point::from(coordinate))
-                    .partial_cmp(&rect_b_center.haversine_distance(&Point::from(coordinate)))
-                    .unwrap()
-            });
+            places.into_iter().next()
+        };
+        // The lookup borrows the tree, which has to be released before marking the hit
+        let id = place.map(|p| p.data);
+        drop(places_containing_point);
+
+        self.stats.gets += 1;
+        let id = match id {
+            Some(id) => id,
+            None => {
+                self.stats.misses += 1;
+                return None;
+            }
+        };
 
-            places.first().map(|f| f.data.clone())
+        self.stats.hits += 1;
+        if overlapping {
+            self.stats.overlapping_hits += 1;
+        }
+
+        self.mark_hit(id, tick)?.data.hits += 1;
+        Some(id)
+    }
+
+    /// Same as `get`, but if no entry contains `coordinate` returns the closest one within `max_distance_meters`.
+    /// Fails if the distance is negative or not finite
+    pub fn get_nearest(
+        &mut self,
+        coordinate: Coordinate<f64>,
+        max_distance_meters: f64,
+    ) -> Result<Option<T>, ValidationError> {
+        let coordinate = validate_coordinate(coordinate)?;
+        // NaN distances panic the nearest neighbour search, infinite ones scan the whole tree
+        if !(max_distance_meters.is_finite() && max_distance_meters >= 0.0) {
+            return Err(ValidationError::InvalidDistance(max_distance_meters));
+        }
+        if let Some(data) = self.get_valid(coordinate) {
+            return Ok(Some(data));
+        }
+
+        let coordinate = truncate_coordinate(coordinate, self.float_precision);
+        let point = Point::from(coordinate);
+        let now = now_millis();
+        let search_radius_2 = search_radius_2(point, max_distance_meters);
+        let entries = &self.entries;
+
+        // The tree orders by distance in degrees, so every candidate within the search radius is measured in meters
+        let nearest = antimeridian_frames(point)
+            .flat_map(|frame| {
+                self.inner
+                    .nearest_neighbor_iter_with_distance_2(&frame.x_y())
+                    .take_while(|(_, distance_2)| *distance_2 <= search_radius_2)
+                    .filter_map(move |(p, _)| {
+                        let Place(place) = entries.get(&p.data)?;
+                        if place.data.is_expired(now) {
+                            return None;
+                        }
+
+                        let distance = place.data.shape.distance_meters(p.geom(), frame);
+                        (distance <= max_distance_meters).then_some((p.data, distance))
+                    })
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b));
+        let Some((id, _)) = nearest else {
+            return Ok(None);
+        };
+
+        let tick = self.tick();
+        self.stats.nearest_hits += 1;
+        Ok(self.mark_hit(id, tick).map(|place| place.data.data.clone()))
+    }
+
+    /// Every live entry containing `coordinate`, best first according to the ranking strategy.
+    /// Unlike `get`, entries are not marked as recently hit
+    pub fn get_all(&self, coordinate: Coordinate<f64>) -> Vec<ContainingEntry<T>> {
+        let coordinate = truncate_coordinate(coordinate, self.float_precision);
+        let now = now_millis();
+
+        let mut containing = self
+            .inner
+            .locate_all_at_point(&coordinate.x_y())
+            .filter_map(|p| {
+                let Place(place) = self.entries.get(&p.data)?;
+                if place.data.is_expired(now) || !place.data.shape.contains(coordinate) {
+                    return None;
+                }
+
+                let score =
+                    self.ranking_strategy
+                        .score(p.data, &place.data, place.geom(), coordinate);
+                let entry = ContainingEntry {
+                    id: p.data,
+                    data: place.data.data.clone(),
+                    bbox: place.geom().into(),
+                    area_meters: rectangle_area_meters(place.geom()),
+                    center_distance_meters: center_distance_meters(place.geom(), coordinate),
+                };
+
+                Some((score, entry))
+            })
+            .collect::<Vec<_>>();
+
+        containing.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+        containing.into_iter().map(|(_, entry)| entry).collect()
+    }
+
+    /// Live entries whose bbox intersects or, for `BboxQuery::Within`, lies inside `bbox`, ordered by id
+    pub fn query_bbox(&self, bbox: BoundingBox, query: BboxQuery) -> Vec<QueriedEntry<T>> {
+        let bbox = unwrap_antimeridian(truncate_bounding_box(bbox, self.float_precision).into());
+        let rect = Rectangle::from_corners(bbox.north_west.x_y(), bbox.south_east.x_y());
+        let now = now_millis();
+
+        // Entries crossing the antimeridian may be found through both halves
+        let ids = indexed_rectangles(&rect)
+            .flat_map(|piece| {
+                self.inner
+                    .locate_in_envelope_intersecting(&AABB::from_corners(
+                        piece.lower(),
+                        piece.upper(),
+                    ))
+            })
+            .map(|indexed| indexed.data)
+            .collect::<BTreeSet<_>>();
+
+        ids.into_iter()
+            .filter_map(|id| {
+                let Place(place) = self.entries.get(&id)?;
+                if place.data.is_expired(now)
+                    || (query == BboxQuery::Within && !rectangle_within(place.geom(), &rect))
+                {
+                    return None;
+                }
+
+                Some(QueriedEntry {
+                    id,
+                    data: place.data.data.clone(),
+                    bbox: place.geom().into(),
+                })
+            })
+            .collect()
+    }
+
+    /// Up to `k` live entries closest to `coordinate`, closest first. Entries containing it are at distance zero
+    pub fn nearest_k(&self, coordinate: Coordinate<f64>, k: usize) -> Vec<NearestEntry<T>> {
+        // The nearest neighbour search panics on NaN distances
+        if k == 0 || !is_finite_coordinate(coordinate) {
+            return Vec::new();
+        }
+
+        let coordinate = truncate_coordinate(coordinate, self.float_precision);
+        let point = Point::from(coordinate);
+        let now = now_millis();
+        let mut nearest: Vec<(EntryId, f64)> = Vec::with_capacity(k.min(self.entries.len()));
+
+        // Entries just across the antimeridian are only close to the point shifted by 360°
+        for frame in antimeridian_frames(point) {
+            for (p, distance_2) in self
+                .inner
+                .nearest_neighbor_iter_with_distance_2(&frame.x_y())
+            {
+                // Nothing further away in degrees can be closer in meters than the current k-th entry
+                let farthest = nearest.last().filter(|_| nearest.len() == k);
+                if farthest.is_some_and(|(_, d)| distance_2 > search_radius_2(point, *d)) {
+                    break;
+                }
+
+                let Some(Place(place)) = self.entries.get(&p.data) else {
+                    continue;
+                };
+                if place.data.is_expired(now) {
+                    continue;
+                }
+
+                let distance = place.data.shape.distance_meters(p.geom(), frame);
+                // An entry may show up in both searches, and both halves of an antimeridian-crossing one
+                // in each, keep the closest
+                if nearest
+                    .iter()
+                    .any(|(id, d)| *id == p.data && *d <= distance)
+                {
+                    continue;
+                }
+                nearest.retain(|(id, _)| *id != p.data);
+
+                let index = nearest.partition_point(|(_, d)| *d <= distance);
+                nearest.insert(index, (p.data, distance));
+                nearest.truncate(k);
+            }
+        }
+
+        nearest
+            .into_iter()
+            .filter_map(|(id, distance_meters)| {
+                let Place(place) = self.entries.get(&id)?;
+                Some(NearestEntry {
+                    id,
+                    data: place.data.data.clone(),
+                    distance_meters,
+                    bbox: place.geom().into(),
+                })
+            })
+            .collect()
+    }
+}
+
+impl<T> CoordinateCache<T>
+where
+    T: Clone + Serialize + DeserializeOwned,
+{
+    /// Serializes the cache, including entry metadata and configuration, into a versioned binary snapshot
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SnapshotError> {
+        let snapshot = Snapshot {
+            float_precision: self.float_precision,
+            normalize_corners: self.normalize_corners,
+            max_entries: self.max_entries,
+            max_memory_bytes: self.max_memory_bytes,
+            eviction_policy: self.eviction_policy,
+            next_id: self.next_id,
+            clock: self.clock,
+            entries: &self.entries,
+        };
+
+        encode_snapshot(&snapshot)
+    }
+
+    /// Rebuilds a cache from a snapshot created by `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let snapshot: Snapshot<BTreeMap<EntryId, Place<T>>> = decode_snapshot(bytes)?;
+        Self::from_snapshot(snapshot)
+    }
+
+    /// Rebuilds a cache from the `metadata` and entries of `CacheChanges`, as stored by an external copy.
+    /// The rebuilt cache already tracks changes relative to that copy
+    pub fn from_parts<I>(metadata: &[u8], entries: I) -> Result<Self, SnapshotError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let snapshot: Snapshot<()> = decode_snapshot(metadata)?;
+        let entries = entries
+            .into_iter()
+            .map(|bytes| {
+                snapshot_options()
+                    .deserialize::<(EntryId, Place<T>)>(bytes.as_ref())
+                    .map_err(SnapshotError::Decode)
+            })
+            .collect::<Result<BTreeMap<_, _>, _>>()?;
+
+        let mut cache = Self::from_snapshot(Snapshot {
+            float_precision: snapshot.float_precision,
+            normalize_corners: snapshot.normalize_corners,
+            max_entries: snapshot.max_entries,
+            max_memory_bytes: snapshot.max_memory_bytes,
+            eviction_policy: snapshot.eviction_policy,
+            next_id: snapshot.next_id,
+            clock: snapshot.clock,
+            entries,
+        })?;
+        cache.changes = Some(ChangeLog::default());
+
+        Ok(cache)
+    }
+
+    /// Returns and resets the changes recorded since the last call. Recency updates from `get` aren't recorded
+    pub fn take_changes(&mut self) -> Result<CacheChanges, SnapshotError> {
+        let metadata = encode_snapshot(&Snapshot {
+            float_precision: self.float_precision,
+            normalize_corners: self.normalize_corners,
+            max_entries: self.max_entries,
+            max_memory_bytes: self.max_memory_bytes,
+            eviction_policy: self.eviction_policy,
+            next_id: self.next_id,
+            clock: self.clock,
+            entries: (),
+        })?;
+
+        let no_changes = ChangeLog::default();
+        let changes = self.changes.as_ref().unwrap_or(&no_changes);
+        let upserted = changes
+            .upserted
+            .iter()
+            .filter_map(|id| {
+                let place = self.entries.get(id)?;
+                let bytes = snapshot_options().serialize(&(id, place));
+                Some(
+                    bytes
+                        .map(|bytes| (*id, bytes))
+                        .map_err(SnapshotError::Encode),
+                )
+            })
+            .collect::<Result<_, _>>()?;
+
+        let cache_changes = CacheChanges {
+            cleared: changes.cleared,
+            metadata,
+            upserted,
+            removed: changes.removed.iter().copied().collect(),
+        };
+
+        // Only forget the changes once everything is encoded, so a failed call can be retried
+        if let Some(changes) = self.changes.as_mut() {
+            *changes = ChangeLog::default();
+        }
+
+        Ok(cache_changes)
+    }
+
+    fn from_snapshot(
+        snapshot: Snapshot<BTreeMap<EntryId, Place<T>>>,
+    ) -> Result<Self, SnapshotError> {
+        // Non-finite corners would make the R-tree panic while loading
+        if let Some(id) = snapshot
+            .entries
+            .iter()
+            .find(|(_, place)| !is_finite_rectangle(place.0.geom()))
+            .map(|(id, _)| *id)
+        {
+            return Err(SnapshotError::InvalidEntry(id));
+        }
+
+        let mut cache = Self {
+            inner: index_entries(&snapshot.entries),
+            entries: snapshot.entries,
+            eviction_order: BTreeSet::new(),
+            inserts_until_purge: 0,
+            next_id: snapshot.next_id,
+            float_precision: snapshot.float_precision,
+            normalize_corners: snapshot.normalize_corners,
+            max_entries: snapshot.max_entries,
+            max_memory_bytes: snapshot.max_memory_bytes,
+            memory_usage: 0,
+            eviction_policy: snapshot.eviction_policy,
+            // Custom strategies can't be serialized, so none are stored in snapshots
+            ranking_strategy: RankingStrategy::default(),
+            clock: snapshot.clock,
+            changes: None,
+            stats: CacheStats::default(),
+            payload_size: inline_size_only,
+        };
+        cache.memory_usage = cache.entries.values().map(|p| cache.entry_size(&p.0)).sum();
+        cache.index_eviction_order();
+
+        Ok(cache)
+    }
+}
+
+impl<T> RankingStrategy<T> {
+    fn score(
+        &self,
+        id: EntryId,
+        address: &Address<T>,
+        rect: &Rectangle<(f64, f64)>,
+        coordinate: Coordinate<f64>,
+    ) -> f64 {
+        match self {
+            RankingStrategy::SmallestArea => rectangle_area_meters(rect),
+            RankingStrategy::CenterDistance => center_distance_meters(rect, coordinate),
+            RankingStrategy::MostRecent => -(id as f64),
+            RankingStrategy::Priority => -f64::from(address.priority),
+            RankingStrategy::Custom(score) => score(address, rect, coordinate),
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for RankingStrategy<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RankingStrategy::SmallestArea => write!(f, "SmallestArea"),
+            RankingStrategy::CenterDistance => write!(f, "CenterDistance"),
+            RankingStrategy::MostRecent => write!(f, "MostRecent"),
+            RankingStrategy::Priority => write!(f, "Priority"),
+            RankingStrategy::Custom(_) => write!(f, "Custom(..)"),
         }
     }
 }
 
-impl Default for CoordinateCache {
+impl<T: Clone> Default for CoordinateCache<T> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl Place {
-    pub fn new(north_west: Point<f64>, south_east: Point<f64>, name: String) -> Self {
-        let rect = Rectangle::from_corners(north_west.x_y(), south_east.x_y());
-        let geom = GeomWithData::new(rect, name);
+impl<T> Place<T> {
+    /// A west side east of the east side means the rectangle crosses the antimeridian.
+    /// Its east side is then stored shifted by 360°, and it's split in two when indexed
+    pub fn new(north_west: Point<f64>, south_east: Point<f64>, address: Address<T>) -> Self {
+        let east = if north_west.x() > south_east.x() {
+            south_east.x() + 360.0
+        } else {
+            south_east.x()
+        };
+        let rect = Rectangle::from_corners(north_west.x_y(), (east, south_east.y()));
+        let geom = GeomWithData::new(rect, address);
 
         Place(geom)
     }
+
+    // Same entry with its corners, or exact shape and its envelope, rounded to `float_precision`
+    fn snapped(self, float_precision: u8) -> Self {
+        let Place(place) = self;
+        let rect = *place.geom();
+        let mut address = place.data;
+
+        let corners = match &mut address.shape {
+            Shape::Rectangle => None,
+            Shape::Polygon(polygon) => {
+                *polygon = truncate_polygon(polygon, float_precision);
+                polygon_envelope(polygon)
+            }
+            Shape::Circle {
+                center,
+                radius_meters,
+            } => {
+                *center = truncate_coordinate(*center, float_precision);
+                Some(circle_envelope(*center, *radius_meters))
+            }
+        };
+
+        match corners {
+            Some((north_west, south_east)) => Place::new(north_west, south_east, address),
+            // Antimeridian-crossing rectangles keep their east side past 180°, so there's nothing to unwrap
+            None => {
+                let lower = truncate_coordinate(Coordinate::from(rect.lower()), float_precision);
+                let upper = truncate_coordinate(Coordinate::from(rect.upper()), float_precision);
+                let rect = Rectangle::from_corners(lower.x_y(), upper.x_y());
+                Place(GeomWithData::new(rect, address))
+            }
+        }
+    }
+}
+
+impl<T> Address<T> {
+    pub fn new(data: T, ttl: Option<Duration>) -> Self {
+        Self {
+            data,
+            inserted_at: now_millis(),
+            ttl,
+            last_hit: 0,
+            shape: Shape::Rectangle,
+            priority: 0,
+            hits: 0,
+        }
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.ttl
+            .map(|ttl| now.saturating_sub(self.inserted_at) as u128 >= ttl.as_millis())
+            .unwrap_or(false)
+    }
+}
+
+impl Shape {
+    // Rectangles are fully checked by the R-tree lookup
+    pub fn contains(&self, coordinate: Coordinate<f64>) -> bool {
+        match self {
+            Shape::Rectangle => true,
+            Shape::Polygon(polygon) => polygon.contains(&Point::from(coordinate)),
+            Shape::Circle {
+                center,
+                radius_meters,
+            } => {
+                Point::from(*center).haversine_distance(&Point::from(coordinate)) <= *radius_meters
+            }
+        }
+    }
+
+    // Haversine distance from `point` to the shape edge, zero if the shape contains it
+    fn distance_meters(&self, rect: &Rectangle<(f64, f64)>, point: Point<f64>) -> f64 {
+        match self {
+            Shape::Rectangle => {
+                let nearest = Point::from(rect.nearest_point(&point.x_y()));
+                point.haversine_distance(&nearest)
+            }
+            Shape::Polygon(polygon) => {
+                if polygon.contains(&point) {
+                    return 0.0;
+                }
+
+                match polygon.closest_point(&point) {
+                    Closest::Intersection(nearest) | Closest::SinglePoint(nearest) => {
+                        point.haversine_distance(&nearest)
+                    }
+                    Closest::Indeterminate => f64::INFINITY,
+                }
+            }
+            Shape::Circle {
+                center,
+                radius_meters,
+            } => (Point::from(*center).haversine_distance(&point) - radius_meters).max(0.0),
+        }
+    }
+
+    fn heap_size(&self) -> usize {
+        match self {
+            Shape::Rectangle => 0,
+            Shape::Polygon(polygon) => {
+                let coords = polygon
+                    .iter()
+                    .map(|part| {
+                        part.exterior().0.capacity()
+                            + part
+                                .interiors()
+                                .iter()
+                                .map(|ring| ring.0.capacity())
+                                .sum::<usize>()
+                    })
+                    .sum::<usize>();
+                polygon.0.capacity() * std::mem::size_of::<Polygon<f64>>()
+                    + coords * std::mem::size_of::<Coordinate<f64>>()
+            }
+            Shape::Circle { .. } => 0,
+        }
+    }
+}
+
+fn is_finite_coordinate(coordinate: Coordinate<f64>) -> bool {
+    coordinate.x.is_finite() && coordinate.y.is_finite()
+}
+
+fn is_finite_rectangle(rect: &Rectangle<(f64, f64)>) -> bool {
+    let (lower, upper) = (rect.lower(), rect.upper());
+    lower.0.is_finite() && lower.1.is_finite() && upper.0.is_finite() && upper.1.is_finite()
+}
+
+// Squared radius, in degrees, of a circle enclosing every point within `meters` of `point`
+fn search_radius_2(point: Point<f64>, meters: f64) -> f64 {
+    match angular_extent(point, meters) {
+        (d_lat, Some(d_lon)) => d_lat * d_lat + d_lon * d_lon,
+        // Reaches a pole, around which every longitude is that close
+        (_, None) => f64::INFINITY,
+    }
+}
+
+// `point`, then the same point shifted by 360° towards the antimeridian. Searching around both finds
+// entries just across it, which are 360° away in degrees. Distances must be measured from the point searched around
+fn antimeridian_frames(point: Point<f64>) -> impl Iterator<Item = Point<f64>> {
+    let shifted = if point.x() < 0.0 {
+        point.x() + 360.0
+    } else {
+        point.x() - 360.0
+    };
+
+    std::iter::once(point).chain(Some(Point::new(shifted, point.y())))
+}
+
+// Splits an entry rectangle crossing the antimeridian into the two halves actually indexed
+fn indexed_rectangles(rect: &Rectangle<(f64, f64)>) -> impl Iterator<Item = Rectangle<(f64, f64)>> {
+    let (lower, upper) = (rect.lower(), rect.upper());
+    let (west, east) = if lower.0 < -180.0 {
+        (lower.0 + 360.0, upper.0 + 360.0)
+    } else {
+        (lower.0, upper.0)
+    };
+
+    if east <= 180.0 {
+        return std::iter::once(*rect).chain(None);
+    }
+
+    let west_half = Rectangle::from_corners((west, lower.1), (180.0, upper.1));
+    let east_half = Rectangle::from_corners((-180.0, lower.1), (east - 360.0, upper.1));
+    std::iter::once(west_half).chain(Some(east_half))
+}
+
+// R-tree holding every indexed piece of `entries`
+fn index_entries<T>(entries: &BTreeMap<EntryId, Place<T>>) -> rstar::RTree<IndexedPlace> {
+    let indexed = entries
+        .iter()
+        .flat_map(|(id, place)| {
+            indexed_rectangles(place.0.geom()).map(move |rect| IndexedPlace::new(rect, *id))
+        })
+        .collect();
+
+    rstar::RTree::bulk_load(indexed)
+}
+
+fn inline_size_only<T>(_: &T) -> usize {
+    0
+}
+
+// Sort key of an entry in `eviction_order`, lower keys are evicted first
+fn eviction_key<T>(policy: EvictionPolicy, id: EntryId, place: &PlaceWithAddress<T>) -> u64 {
+    match policy {
+        EvictionPolicy::LeastRecentlyHit => place.data.last_hit,
+        EvictionPolicy::Oldest => id,
+        // Areas are never negative, so their bits sort like the areas themselves
+        EvictionPolicy::LargestArea => !rectangle_area_meters(place.geom()).to_bits(),
+    }
+}
+
+// North west and south east corners of the envelope, `None` for an empty polygon
+fn polygon_envelope(polygon: &MultiPolygon<f64>) -> Option<(Point<f64>, Point<f64>)> {
+    let envelope = polygon.bounding_rect()?;
+    Some((
+        Point::new(envelope.min().x, envelope.max().y),
+        Point::new(envelope.max().x, envelope.min().y),
+    ))
+}
+
+// Same radius geo uses for haversine distances
+const MEAN_EARTH_RADIUS: f64 = 6371008.8;
+
+// Latitude and longitude spans, in degrees, of the points within `meters` of `point`.
+// The longitude span is `None` when those points reach a pole, and so every longitude
+fn angular_extent(point: Point<f64>, meters: f64) -> (f64, Option<f64>) {
+    let distance = meters / MEAN_EARTH_RADIUS;
+    let latitude = point.y().to_radians();
+    let d_lat = distance.to_degrees();
+
+    // Widest longitude offset of a small circle, reached east and west of the center's meridian
+    let sin_d_lon = distance.sin() / latitude.cos();
+    let d_lon = (distance < std::f64::consts::FRAC_PI_2 && sin_d_lon < 1.0)
+        .then(|| sin_d_lon.asin().to_degrees());
+
+    if point.y() + d_lat >= 90.0 || point.y() - d_lat <= -90.0 {
+        return (d_lat, None);
+    }
+
+    (d_lat, d_lon)
+}
+
+// North west and south east corners of the envelope. Circles around a pole span every longitude
+fn circle_envelope(center: Coordinate<f64>, radius_meters: f64) -> (Point<f64>, Point<f64>) {
+    let (d_lat, d_lon) = angular_extent(Point::from(center), radius_meters);
+    let north = (center.y + d_lat).min(90.0);
+    let south = (center.y - d_lat).max(-90.0);
+
+    match d_lon {
+        Some(d_lon) => (
+            Point::new(center.x - d_lon, north),
+            Point::new(center.x + d_lon, south),
+        ),
+        None => (Point::new(-180.0, north), Point::new(180.0, south)),
+    }
+}
+
+// Shifts the east side of a box crossing the antimeridian by 360°, so plain rectangle math works on it
+fn unwrap_antimeridian(bbox: PointBoundingBox) -> PointBoundingBox {
+    if bbox.north_west.x() <= bbox.north_east.x() {
+        return bbox;
+    }
+
+    PointBoundingBox {
+        north_east: Point::new(bbox.north_east.x() + 360.0, bbox.north_east.y()),
+        south_east: Point::new(bbox.south_east.x() + 360.0, bbox.south_east.y()),
+        ..bbox
+    }
+}
+
+// Moves a point into the longitude range of a box returned by `unwrap_antimeridian`
+fn unwrap_point(point: Point<f64>, bbox: &PointBoundingBox) -> Point<f64> {
+    if bbox.north_east.x() > 180.0 && point.x() < bbox.north_west.x() {
+        Point::new(point.x() + 360.0, point.y())
+    } else {
+        point
+    }
+}
+
+fn normalize_longitude(longitude: f64) -> f64 {
+    if longitude > 180.0 {
+        longitude - 360.0
+    } else if longitude < -180.0 {
+        longitude + 360.0
+    } else {
+        longitude
+    }
+}
+
+fn normalize_point_bounding_box(bbox: PointBoundingBox) -> PointBoundingBox {
+    let normalize = |p: Point<f64>| Point::new(normalize_longitude(p.x()), p.y());
+
+    PointBoundingBox {
+        south_west: normalize(bbox.south_west),
+        south_east: normalize(bbox.south_east),
+        north_west: normalize(bbox.north_west),
+        north_east: normalize(bbox.north_east),
+    }
+}
+
+fn snapshot_options() -> impl Options {
+    bincode::DefaultOptions::new().with_little_endian()
+}
+
+fn encode_snapshot<E: Serialize>(snapshot: &Snapshot<E>) -> Result<Vec<u8>, SnapshotError> {
+    let mut bytes = SNAPSHOT_MAGIC.to_vec();
+    bytes.push(SNAPSHOT_VERSION);
+    // Only fails if a payload's `Serialize` implementation does
+    snapshot_options()
+        .serialize_into(&mut bytes, snapshot)
+        .map_err(SnapshotError::Encode)?;
+    Ok(bytes)
+}
+
+fn decode_snapshot<E: DeserializeOwned>(bytes: &[u8]) -> Result<Snapshot<E>, SnapshotError> {
+    let body = bytes
+        .strip_prefix(SNAPSHOT_MAGIC)
+        .ok_or(SnapshotError::InvalidHeader)?;
+
+    let (version, body) = body.split_first().ok_or(SnapshotError::InvalidHeader)?;
+    if *version != SNAPSHOT_VERSION {
+        return Err(SnapshotError::UnsupportedVersion(*version));
+    }
+
+    snapshot_options()
+        .deserialize(body)
+        .map_err(SnapshotError::Decode)
+}
+
+fn rectangle_area_meters(rect: &Rectangle<(f64, f64)>) -> f64 {
+    let (west, south) = rect.lower();
+    let (east, north) = rect.upper();
+    let north_west = Point::new(west, north);
+    let north_east = Point::new(east, north);
+    let south_east = Point::new(east, south);
+
+    north_east.haversine_distance(&north_west) * north_east.haversine_distance(&south_east)
+}
+
+// Both rectangles may have been unwrapped past 180°, so `inner` is also compared shifted a full turn
+fn rectangle_within(inner: &Rectangle<(f64, f64)>, outer: &Rectangle<(f64, f64)>) -> bool {
+    let (outer_lower, outer_upper) = (outer.lower(), outer.upper());
+    let (lower, upper) = (inner.lower(), inner.upper());
+    let latitude_within = lower.1 >= outer_lower.1 && upper.1 <= outer_upper.1;
+
+    latitude_within
+        && [0.0, 360.0]
+            .iter()
+            .any(|shift| lower.0 + shift >= outer_lower.0 && upper.0 + shift <= outer_upper.0)
+}
+
+fn center_distance_meters(rect: &Rectangle<(f64, f64)>, coordinate: Coordinate<f64>) -> f64 {
+    let center: Point<f64> = Rect::new(rect.upper(), rect.lower()).center().into();
+    center.haversine_distance(&Point::from(coordinate))
+}
+
+// SystemTime::now panics on wasm32-unknown-unknown, so ask the JS host for the time instead
+#[cfg(target_arch = "wasm32")]
+fn now_millis() -> u64 {
+    js_sys::Date::now() as u64
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
 }
 
 #[inline(always)]
@@ -377,33 +2010,19 @@ pub fn truncate_point_bounding_box(bbox: PointBoundingBox, decimal_places: u8) -
     }
 }
 
+pub fn truncate_polygon(polygon: &MultiPolygon<f64>, decimal_places: u8) -> MultiPolygon<f64> {
+    polygon.map_coords(|&(x, y)| truncate_coordinate(Coordinate { x, y }, decimal_places).x_y())
+}
+
 impl TryFrom<Vec<f64>> for BoundingBox {
-    type Error = BoundingBoxConversionError;
+    type Error = ValidationError;
 
     fn try_from(bounding_box: Vec<f64>) -> Result<Self, Self::Error> {
-        let south = bounding_box
-            .get(0)
-            .ok_or_else(|| BoundingBoxConversionError {
-                _bounding_box: bounding_box.clone(),
-            })?;
-
-        let north = bounding_box
-            .get(1)
-            .ok_or_else(|| BoundingBoxConversionError {
-                _bounding_box: bounding_box.clone(),
-            })?;
-
-        let west = bounding_box
-            .get(2)
-            .ok_or_else(|| BoundingBoxConversionError {
-                _bounding_box: bounding_box.clone(),
-            })?;
-
-        let east = bounding_box
-            .get(3)
-            .ok_or_else(|| BoundingBoxConversionError {
-                _bounding_box: bounding_box.clone(),
-            })?;
+        let missing = || ValidationError::MissingValues(bounding_box.clone());
+        let south = bounding_box.first().ok_or_else(missing)?;
+        let north = bounding_box.get(1).ok_or_else(missing)?;
+        let west = bounding_box.get(2).ok_or_else(missing)?;
+        let east = bounding_box.get(3).ok_or_else(missing)?;
 
         Ok(Self {
             south_west: Point::new(*west, *south).into(),
@@ -414,6 +2033,112 @@ impl TryFrom<Vec<f64>> for BoundingBox {
     }
 }
 
+/// Checks that `coordinate` is finite and within latitude and longitude bounds
+pub fn validate_coordinate(
+    coordinate: Coordinate<f64>,
+) -> Result<Coordinate<f64>, ValidationError> {
+    if !is_finite_coordinate(coordinate) {
+        return Err(ValidationError::NonFinite(coordinate));
+    }
+
+    if !(-90.0..=90.0).contains(&coordinate.y) {
+        return Err(ValidationError::LatitudeOutOfRange(coordinate.y));
+    }
+
+    if !(-180.0..=180.0).contains(&coordinate.x) {
+        return Err(ValidationError::LongitudeOutOfRange(coordinate.x));
+    }
+
+    Ok(coordinate)
+}
+
+/// Checks that every corner is valid and that together they form an axis-aligned rectangle.
+/// A west side east of the east side is accepted as crossing the antimeridian.
+/// With `normalize`, a south side north of the north side is swapped instead of rejected
+pub fn validate_bounding_box(
+    bbox: BoundingBox,
+    normalize: bool,
+) -> Result<BoundingBox, ValidationError> {
+    for corner in [
+        bbox.south_west,
+        bbox.south_east,
+        bbox.north_west,
+        bbox.north_east,
+    ] {
+        validate_coordinate(corner)?;
+    }
+
+    let is_axis_aligned = bbox.south_west.y == bbox.south_east.y
+        && bbox.north_west.y == bbox.north_east.y
+        && bbox.south_west.x == bbox.north_west.x
+        && bbox.south_east.x == bbox.north_east.x;
+    if !is_axis_aligned {
+        return Err(ValidationError::NotAxisAligned(bbox));
+    }
+
+    if bbox.south_west.y <= bbox.north_west.y {
+        return Ok(bbox);
+    }
+
+    if !normalize {
+        return Err(ValidationError::SwappedCorners(bbox));
+    }
+
+    Ok(BoundingBox {
+        south_west: bbox.north_west,
+        south_east: bbox.north_east,
+        north_west: bbox.south_west,
+        north_east: bbox.south_east,
+    })
+}
+
+/// Parses a GeoJSON `Polygon` or `MultiPolygon` geometry, such as Nominatim's `polygon_geojson`
+pub fn polygon_from_geojson(
+    geometry: &serde_json::Value,
+) -> Result<MultiPolygon<f64>, GeoJsonConversionError> {
+    let error = || GeoJsonConversionError {
+        _geometry: geometry.clone(),
+    };
+
+    // [[lon, lat], ...]
+    let ring = |ring: &serde_json::Value| -> Option<LineString<f64>> {
+        ring.as_array()?
+            .iter()
+            .map(|position| {
+                let position = position.as_array()?;
+                Some(Coordinate {
+                    x: position.first()?.as_f64()?,
+                    y: position.get(1)?.as_f64()?,
+                })
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(LineString::from)
+    };
+
+    // [exterior, hole, ...]
+    let polygon = |rings: &serde_json::Value| -> Option<Polygon<f64>> {
+        let mut rings = rings
+            .as_array()?
+            .iter()
+            .map(ring)
+            .collect::<Option<Vec<_>>>()?
+            .into_iter();
+        let exterior = rings.next()?;
+        Some(Polygon::new(exterior, rings.collect()))
+    };
+
+    let coordinates = geometry.get("coordinates").ok_or_else(error)?;
+    match geometry.get("type").and_then(|t| t.as_str()) {
+        Some("Polygon") => polygon(coordinates).map(MultiPolygon::from),
+        Some("MultiPolygon") => coordinates
+            .as_array()
+            .and_then(|parts| parts.iter().map(polygon).collect::<Option<Vec<_>>>())
+            .map(MultiPolygon),
+        _ => None,
+    }
+    .ok_or_else(error)
+}
+
 impl From<BoundingBox> for Vec<Coordinate<f64>> {
     fn from(bbox: BoundingBox) -> Self {
         vec![
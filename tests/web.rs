@@ -3,10 +3,15 @@
 #![cfg(target_arch = "wasm32")]
 
 extern crate wasm_bindgen_test;
+use std::collections::BTreeMap;
 use std::convert::TryInto;
+use std::sync::Arc;
 use wasm_bindgen_test::*;
-use wasm_rtree_cache::rtree::BoundingBox;
-use wasm_rtree_cache::{Bbox, Coordinate};
+use wasm_rtree_cache::rtree::{
+    self, BboxQuery, BoundingBox, BoundingBoxSetResult, CoordinateCache, PayloadSize,
+    RankingStrategy, ValidationError,
+};
+use wasm_rtree_cache::{Bbox, CacheHandle, Coordinate, EvictionPolicy};
 wasm_bindgen_test_configure!(run_in_browser);
 
 #[wasm_bindgen_test]
@@ -23,9 +28,9 @@ pub fn contains_point() {
         y: -30.0126987,
     };
 
-    wasm_rtree_cache::clear();
-    wasm_rtree_cache::set_bbox(data.clone(), bbox, Some(reference_point));
-    let address = wasm_rtree_cache::get(reference_point).unwrap();
+    wasm_rtree_cache::clear().unwrap();
+    wasm_rtree_cache::set_bbox(data.clone(), bbox, Some(reference_point), None).unwrap();
+    let address = wasm_rtree_cache::get(reference_point).unwrap().unwrap();
 
     assert_eq!(address, data);
 }
@@ -44,9 +49,9 @@ pub fn do_not_contains_point() {
         y: -29.0126987,
     };
 
-    wasm_rtree_cache::clear();
-    wasm_rtree_cache::set_bbox(data.clone(), bbox, Some(reference_point));
-    let address = wasm_rtree_cache::get(reference_point);
+    wasm_rtree_cache::clear().unwrap();
+    wasm_rtree_cache::set_bbox(data.clone(), bbox, Some(reference_point), None).unwrap();
+    let address = wasm_rtree_cache::get(reference_point).unwrap();
 
     assert!(address.is_none());
 }
@@ -66,10 +71,17 @@ pub fn overlapping_bbox_huge() {
         y: -30.0126987,
     };
 
-    wasm_rtree_cache::clear();
-    wasm_rtree_cache::set_bbox(data.clone(), small_bbox.into(), Some(reference_point));
-    wasm_rtree_cache::set_bbox("HUGE".to_string(), huge_bbox.into(), Some(reference_point));
-    let address = wasm_rtree_cache::get(reference_point).unwrap();
+    wasm_rtree_cache::clear().unwrap();
+    wasm_rtree_cache::set_bbox(data.clone(), small_bbox.into(), Some(reference_point), None)
+        .unwrap();
+    wasm_rtree_cache::set_bbox(
+        "HUGE".to_string(),
+        huge_bbox.into(),
+        Some(reference_point),
+        None,
+    )
+    .unwrap();
+    let address = wasm_rtree_cache::get(reference_point).unwrap().unwrap();
 
     assert_eq!(address, data);
 }
@@ -87,9 +99,1079 @@ pub fn set_bbox_huge() {
 
     let max_len = 10000.0;
 
-    wasm_rtree_cache::clear();
-    wasm_rtree_cache::set_bbox(data.clone(), bbox.into(), Some(reference_point));
-    let result = wasm_rtree_cache::get(reference_point).unwrap();
+    wasm_rtree_cache::clear().unwrap();
+    wasm_rtree_cache::set_bbox(data.clone(), bbox.into(), Some(reference_point), None).unwrap();
+    let result = wasm_rtree_cache::get(reference_point).unwrap().unwrap();
 
     assert_eq!(result, data);
 }
+
+#[wasm_bindgen_test]
+pub fn expired_entry_is_ignored() {
+    let bbox: BoundingBox = vec![-30.0146987, -30.0115462, -51.1833537, -51.1832816]
+        .try_into()
+        .unwrap();
+
+    let reference_point = Coordinate {
+        x: -51.1833500,
+        y: -30.0126987,
+    };
+
+    wasm_rtree_cache::clear().unwrap();
+    wasm_rtree_cache::set_bbox(
+        "Expired".to_string(),
+        bbox.into(),
+        Some(reference_point),
+        Some(0.0),
+    )
+    .unwrap();
+
+    assert!(wasm_rtree_cache::get(reference_point).unwrap().is_none());
+    assert_eq!(wasm_rtree_cache::purge_expired().unwrap(), 1);
+}
+
+#[wasm_bindgen_test]
+pub fn evicts_least_recently_hit() {
+    let first: BoundingBox = vec![-30.02, -30.01, -51.19, -51.18].try_into().unwrap();
+    let second: BoundingBox = vec![-31.02, -31.01, -52.19, -52.18].try_into().unwrap();
+    let third: BoundingBox = vec![-32.02, -32.01, -53.19, -53.18].try_into().unwrap();
+
+    let first_point = Coordinate::new(-30.015, -51.185);
+    let second_point = Coordinate::new(-31.015, -52.185);
+    let third_point = Coordinate::new(-32.015, -53.185);
+
+    wasm_rtree_cache::clear().unwrap();
+    wasm_rtree_cache::set_capacity(Some(2)).unwrap();
+    wasm_rtree_cache::set_bbox("First".to_string(), first.into(), None, None).unwrap();
+    wasm_rtree_cache::set_bbox("Second".to_string(), second.into(), None, None).unwrap();
+
+    // Hitting the first entry makes the second one the least recently used
+    assert!(wasm_rtree_cache::get(first_point).unwrap().is_some());
+    wasm_rtree_cache::set_bbox("Third".to_string(), third.into(), None, None).unwrap();
+    wasm_rtree_cache::set_capacity(None).unwrap();
+
+    assert_eq!(
+        wasm_rtree_cache::get(first_point).unwrap().unwrap(),
+        "First"
+    );
+    assert!(wasm_rtree_cache::get(second_point).unwrap().is_none());
+    assert_eq!(
+        wasm_rtree_cache::get(third_point).unwrap().unwrap(),
+        "Third"
+    );
+}
+
+#[wasm_bindgen_test]
+pub fn evicts_oldest_even_if_recently_hit() {
+    let first: BoundingBox = vec![-30.02, -30.01, -51.19, -51.18].try_into().unwrap();
+    let second: BoundingBox = vec![-31.02, -31.01, -52.19, -52.18].try_into().unwrap();
+    let third: BoundingBox = vec![-32.02, -32.01, -53.19, -53.18].try_into().unwrap();
+
+    let mut cache = CoordinateCache::<String>::new_with_capacity(2);
+    cache.set_eviction_policy(rtree::EvictionPolicy::Oldest);
+    let first_id = cache.set("First".to_string(), first, None).unwrap().id;
+    let second_id = cache.set("Second".to_string(), second, None).unwrap().id;
+
+    // Usually set in the same millisecond, the first entry is still the oldest
+    cache.get(Coordinate::new(-31.015, -52.185).into()).unwrap();
+    cache.get(Coordinate::new(-30.015, -51.185).into()).unwrap();
+    cache.set("Third".to_string(), third, None).unwrap();
+
+    assert!(cache.get_by_id(first_id).is_none());
+    assert!(cache.get_by_id(second_id).is_some());
+
+    // Switching policies reorders the existing entries
+    cache.get(Coordinate::new(-31.015, -52.185).into()).unwrap();
+    cache.set_eviction_policy(rtree::EvictionPolicy::LeastRecentlyHit);
+    cache.set("Fourth".to_string(), first, None).unwrap();
+
+    assert!(cache.get_by_id(second_id).is_some());
+    assert!(cache
+        .get(Coordinate::new(-32.015, -53.185).into())
+        .unwrap()
+        .is_none());
+}
+
+#[wasm_bindgen_test]
+pub fn evicts_largest_area_over_memory_budget() {
+    let small: BoundingBox = vec![-30.02, -30.01, -51.19, -51.18].try_into().unwrap();
+    let huge: BoundingBox = vec![-40.0, -20.0, -60.0, -40.0].try_into().unwrap();
+    let other: BoundingBox = vec![-32.02, -32.01, -53.19, -53.18].try_into().unwrap();
+
+    wasm_rtree_cache::clear().unwrap();
+    wasm_rtree_cache::set_eviction_policy(EvictionPolicy::LargestArea).unwrap();
+    wasm_rtree_cache::set_bbox("Small".to_string(), small.into(), None, None).unwrap();
+    wasm_rtree_cache::set_bbox("Large".to_string(), huge.into(), None, None).unwrap();
+
+    // Only room for two entries of this size
+    let budget = wasm_rtree_cache::memory_usage().unwrap();
+    wasm_rtree_cache::set_memory_budget(Some(budget)).unwrap();
+    wasm_rtree_cache::set_bbox("Other".to_string(), other.into(), None, None).unwrap();
+    wasm_rtree_cache::set_memory_budget(None).unwrap();
+    wasm_rtree_cache::set_eviction_policy(EvictionPolicy::LeastRecentlyHit).unwrap();
+
+    assert_eq!(wasm_rtree_cache::memory_usage().unwrap(), budget);
+    assert_eq!(
+        wasm_rtree_cache::get(Coordinate::new(-30.015, -51.185))
+            .unwrap()
+            .unwrap(),
+        "Small"
+    );
+    assert_eq!(
+        wasm_rtree_cache::get(Coordinate::new(-32.015, -53.185))
+            .unwrap()
+            .unwrap(),
+        "Other"
+    );
+    assert!(wasm_rtree_cache::get(Coordinate::new(-25.0, -45.0))
+        .unwrap()
+        .is_none());
+}
+
+#[wasm_bindgen_test]
+pub fn remove_containing_keeps_other_entries() {
+    let small_bbox: BoundingBox = vec![-30.0146987, -30.0115462, -51.1833537, -51.1832816]
+        .try_into()
+        .unwrap();
+    let other_bbox: BoundingBox = vec![-32.02, -32.01, -53.19, -53.18].try_into().unwrap();
+
+    let reference_point = Coordinate {
+        x: -51.1833500,
+        y: -30.0126987,
+    };
+    let other_point = Coordinate::new(-32.015, -53.185);
+
+    wasm_rtree_cache::clear().unwrap();
+    wasm_rtree_cache::set_bbox("Small".to_string(), small_bbox.into(), None, None).unwrap();
+    wasm_rtree_cache::set_bbox("Other".to_string(), other_bbox.into(), None, None).unwrap();
+
+    assert_eq!(
+        wasm_rtree_cache::remove_containing(reference_point).unwrap(),
+        1
+    );
+    assert!(wasm_rtree_cache::get(reference_point).unwrap().is_none());
+    assert_eq!(
+        wasm_rtree_cache::get(other_point).unwrap().unwrap(),
+        "Other"
+    );
+
+    let other_bbox: BoundingBox = vec![-33.0, -32.015, -54.0, -53.185].try_into().unwrap();
+    assert_eq!(
+        wasm_rtree_cache::remove_intersecting(other_bbox.into()).unwrap(),
+        1
+    );
+    assert!(wasm_rtree_cache::get(other_point).unwrap().is_none());
+}
+
+#[wasm_bindgen_test]
+pub fn update_and_remove_by_id() {
+    let bbox: BoundingBox = vec![-30.0146987, -30.0115462, -51.1833537, -51.1832816]
+        .try_into()
+        .unwrap();
+
+    let reference_point = Coordinate {
+        x: -51.1833500,
+        y: -30.0126987,
+    };
+
+    wasm_rtree_cache::clear().unwrap();
+    let id = wasm_rtree_cache::set_bbox("Wrong".to_string(), bbox.into(), None, None).unwrap();
+    assert_eq!(wasm_rtree_cache::get_by_id(id).unwrap().unwrap(), "Wrong");
+
+    assert!(wasm_rtree_cache::update_data(id, "Right".to_string()).unwrap());
+    assert_eq!(
+        wasm_rtree_cache::get(reference_point).unwrap().unwrap(),
+        "Right"
+    );
+
+    assert_eq!(wasm_rtree_cache::remove(id).unwrap().unwrap(), "Right");
+    assert!(wasm_rtree_cache::get(reference_point).unwrap().is_none());
+    assert!(wasm_rtree_cache::get_by_id(id).unwrap().is_none());
+    assert!(!wasm_rtree_cache::update_data(id, "Gone".to_string()).unwrap());
+}
+
+#[wasm_bindgen_test]
+pub fn invalid_ids_are_rejected() {
+    let bbox: BoundingBox = vec![-30.02, -30.01, -51.02, -51.01].try_into().unwrap();
+
+    wasm_rtree_cache::clear().unwrap();
+    let id = wasm_rtree_cache::set_bbox("Kept".to_string(), bbox.into(), None, None).unwrap();
+
+    for invalid in [-1.0, f64::NAN, f64::INFINITY, id + 0.7, 2f64.powi(60)] {
+        assert!(wasm_rtree_cache::get_by_id(invalid).is_err());
+        assert!(wasm_rtree_cache::update_data(invalid, "Wrong".to_string()).is_err());
+        assert!(wasm_rtree_cache::set_priority(invalid, 1).is_err());
+        assert!(wasm_rtree_cache::hit_count(invalid).is_err());
+        assert!(wasm_rtree_cache::remove(invalid).is_err());
+    }
+
+    assert_eq!(wasm_rtree_cache::get_by_id(id).unwrap().unwrap(), "Kept");
+}
+
+#[wasm_bindgen_test]
+pub fn snapshot_roundtrip() {
+    let bbox: BoundingBox = vec![-30.0146987, -30.0115462, -51.1833537, -51.1832816]
+        .try_into()
+        .unwrap();
+
+    let reference_point = Coordinate {
+        x: -51.1833500,
+        y: -30.0126987,
+    };
+
+    wasm_rtree_cache::clear().unwrap();
+    let id =
+        wasm_rtree_cache::set_bbox("Porto Alegre".to_string(), bbox.into(), None, None).unwrap();
+    let snapshot = wasm_rtree_cache::export_snapshot().unwrap();
+
+    wasm_rtree_cache::clear().unwrap();
+    assert!(wasm_rtree_cache::get(reference_point).unwrap().is_none());
+
+    wasm_rtree_cache::set_ranking_strategy(wasm_rtree_cache::RankingStrategy::MostRecent).unwrap();
+    let older: BoundingBox = vec![-30.02, -30.0, -51.19, -51.17].try_into().unwrap();
+    let newer: BoundingBox = vec![-30.5, -29.9, -51.6, -51.1].try_into().unwrap();
+    wasm_rtree_cache::set_bbox("Older".to_string(), older.into(), None, None).unwrap();
+    wasm_rtree_cache::set_bbox("Newer".to_string(), newer.into(), None, None).unwrap();
+    let ranked = wasm_rtree_cache::export_snapshot().unwrap();
+    wasm_rtree_cache::import_snapshot(&ranked).unwrap();
+    // The ranking strategy isn't part of the snapshot and survives the import
+    assert_eq!(
+        wasm_rtree_cache::get(reference_point).unwrap().unwrap(),
+        "Newer"
+    );
+    wasm_rtree_cache::set_ranking_strategy(wasm_rtree_cache::RankingStrategy::CenterDistance)
+        .unwrap();
+
+    wasm_rtree_cache::import_snapshot(&snapshot).unwrap();
+    assert_eq!(
+        wasm_rtree_cache::get(reference_point).unwrap().unwrap(),
+        "Porto Alegre"
+    );
+    assert_eq!(
+        wasm_rtree_cache::get_by_id(id).unwrap().unwrap(),
+        "Porto Alegre"
+    );
+
+    assert!(wasm_rtree_cache::import_snapshot(&snapshot[1..]).is_err());
+}
+
+#[wasm_bindgen_test]
+pub fn incremental_changes_rebuild_cache() {
+    let first: BoundingBox = vec![-30.02, -30.01, -51.19, -51.18].try_into().unwrap();
+    let second: BoundingBox = vec![-31.02, -31.01, -52.19, -52.18].try_into().unwrap();
+
+    let mut cache = CoordinateCache::new();
+    cache.track_changes();
+    let first_id = cache.set("First".to_string(), first, None).unwrap().id;
+    cache.set("Second".to_string(), second, None).unwrap();
+
+    // Stands in for an external store keyed by entry id
+    let mut stored = BTreeMap::new();
+    let changes = cache.take_changes().unwrap();
+    assert!(changes.cleared);
+    stored.extend(changes.upserted);
+
+    cache.remove(first_id);
+    let changes = cache.take_changes().unwrap();
+    assert!(!changes.cleared);
+    assert!(changes.upserted.is_empty());
+    assert_eq!(changes.removed, vec![first_id]);
+    for id in changes.removed {
+        stored.remove(&id);
+    }
+
+    let mut restored =
+        CoordinateCache::<String>::from_parts(&changes.metadata, stored.values()).unwrap();
+    assert_eq!(restored.len(), 1);
+    assert!(restored
+        .get(Coordinate::new(-30.015, -51.185).into())
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        restored
+            .get(Coordinate::new(-31.015, -52.185).into())
+            .unwrap()
+            .unwrap(),
+        "Second"
+    );
+}
+
+#[cfg(feature = "indexeddb")]
+#[wasm_bindgen_test]
+pub async fn indexeddb_roundtrip() {
+    let bbox: BoundingBox = vec![-30.0146987, -30.0115462, -51.1833537, -51.1832816]
+        .try_into()
+        .unwrap();
+
+    let reference_point = Coordinate {
+        x: -51.1833500,
+        y: -30.0126987,
+    };
+
+    wasm_rtree_cache::clear().unwrap();
+    wasm_rtree_cache::set_bbox("Porto Alegre".to_string(), bbox.into(), None, None).unwrap();
+    wasm_rtree_cache::indexeddb::persist().await.unwrap();
+
+    wasm_rtree_cache::clear().unwrap();
+    assert!(wasm_rtree_cache::indexeddb::restore().await.unwrap());
+    assert_eq!(
+        wasm_rtree_cache::get(reference_point).unwrap().unwrap(),
+        "Porto Alegre"
+    );
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Street {
+    name: &'static str,
+    number: u32,
+}
+
+#[wasm_bindgen_test]
+pub fn generic_payload() {
+    let bbox: BoundingBox = vec![-31.0, -20.0, -50.0, -40.0].try_into().unwrap();
+    let street = Street {
+        name: "Av. Ipiranga",
+        number: 6681,
+    };
+
+    let mut cache = CoordinateCache::new();
+    let result = cache
+        .set_with_max_len(
+            street.clone(),
+            bbox,
+            Coordinate::new(-25.111111, -45.222222).into(),
+            Some(10000.0),
+        )
+        .unwrap();
+
+    assert!(matches!(result, BoundingBoxSetResult::SetTruncated(_)));
+    assert_eq!(
+        cache
+            .get(Coordinate::new(-25.111111, -45.222222).into())
+            .unwrap(),
+        Some(street)
+    );
+
+    // Owned heap memory only counts once payloads can be measured
+    let mut strings = CoordinateCache::new();
+    strings.set("x".repeat(1000), bbox, None).unwrap();
+    let inline = strings.memory_usage();
+    strings.set_payload_size(String::heap_size);
+    assert_eq!(strings.memory_usage(), inline + 1000);
+}
+
+#[wasm_bindgen_test]
+pub fn structured_value_roundtrip() {
+    let bbox: BoundingBox = vec![-30.0146987, -30.0115462, -51.1833537, -51.1832816]
+        .try_into()
+        .unwrap();
+
+    let reference_point = Coordinate {
+        x: -51.1833500,
+        y: -30.0126987,
+    };
+
+    let address = js_sys::JSON::parse(r#"{"road":"Av. Ipiranga","house_number":"6681"}"#).unwrap();
+
+    wasm_rtree_cache::clear().unwrap();
+    let text_id = wasm_rtree_cache::set_bbox("Text".to_string(), bbox.into(), None, None).unwrap();
+    let value_id = wasm_rtree_cache::set_bbox_value(address, bbox.into(), None, None).unwrap();
+
+    // Both kinds share the same cache, so ids never collide
+    assert_ne!(text_id, value_id);
+    wasm_rtree_cache::set_ranking_strategy(wasm_rtree_cache::RankingStrategy::MostRecent).unwrap();
+
+    let value = wasm_rtree_cache::get_value(reference_point).unwrap();
+    let road = js_sys::Reflect::get(&value, &"road".into()).unwrap();
+    assert_eq!(road.as_string().unwrap(), "Av. Ipiranga");
+    // Strings get the serialized value
+    assert_eq!(
+        wasm_rtree_cache::get(reference_point).unwrap().unwrap(),
+        r#"{"house_number":"6681","road":"Av. Ipiranga"}"#
+    );
+
+    wasm_rtree_cache::remove(value_id).unwrap();
+    assert_eq!(
+        wasm_rtree_cache::get_value(reference_point)
+            .unwrap()
+            .as_string()
+            .unwrap(),
+        "Text"
+    );
+    wasm_rtree_cache::set_ranking_strategy(wasm_rtree_cache::RankingStrategy::CenterDistance)
+        .unwrap();
+    assert!(wasm_rtree_cache::get_value(Coordinate::new(-29.0, -51.0))
+        .unwrap()
+        .is_undefined());
+}
+
+#[wasm_bindgen_test]
+pub fn polygon_exact_containment() {
+    // Right triangle whose envelope is the square (0, 0) - (1, 1)
+    let geometry =
+        js_sys::JSON::parse(r#"{"type":"Polygon","coordinates":[[[0,0],[1,0],[0,1],[0,0]]]}"#)
+            .unwrap();
+
+    wasm_rtree_cache::clear().unwrap();
+    wasm_rtree_cache::set_polygon_geojson("Triangle".to_string(), geometry, None).unwrap();
+
+    assert_eq!(
+        wasm_rtree_cache::get(Coordinate::new(0.2, 0.2))
+            .unwrap()
+            .unwrap(),
+        "Triangle"
+    );
+    // Inside the envelope but outside the triangle
+    assert!(wasm_rtree_cache::get(Coordinate::new(0.8, 0.8))
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        wasm_rtree_cache::remove_containing(Coordinate::new(0.8, 0.8)).unwrap(),
+        0
+    );
+
+    // Two unit squares, a degree apart
+    let geometry = js_sys::JSON::parse(
+        r#"{"type":"MultiPolygon","coordinates":[
+            [[[10,0],[11,0],[11,1],[10,1],[10,0]]],
+            [[[12,0],[13,0],[13,1],[12,1],[12,0]]]
+        ]}"#,
+    )
+    .unwrap();
+    wasm_rtree_cache::set_polygon_geojson("Islands".to_string(), geometry, None).unwrap();
+
+    for lon in [10.5, 12.5] {
+        assert_eq!(
+            wasm_rtree_cache::get(Coordinate::new(0.5, lon))
+                .unwrap()
+                .unwrap(),
+            "Islands"
+        );
+    }
+    // Between both parts
+    assert!(wasm_rtree_cache::get(Coordinate::new(0.5, 11.5))
+        .unwrap()
+        .is_none());
+
+    let point = js_sys::JSON::parse(r#"{"type":"Point","coordinates":[0,0]}"#).unwrap();
+    assert!(wasm_rtree_cache::set_polygon_geojson("Point".to_string(), point, None).is_err());
+}
+
+#[wasm_bindgen_test]
+pub fn circle_haversine_containment() {
+    let center = Coordinate::new(-30.0, -51.0);
+
+    wasm_rtree_cache::clear().unwrap();
+    wasm_rtree_cache::set_circle("Circle".to_string(), center, 1000.0, None).unwrap();
+
+    assert_eq!(wasm_rtree_cache::get(center).unwrap().unwrap(), "Circle");
+    // ~700m north
+    assert_eq!(
+        wasm_rtree_cache::get(Coordinate::new(-29.9937, -51.0))
+            .unwrap()
+            .unwrap(),
+        "Circle"
+    );
+    // ~990m north and ~990m east, inside the envelope but outside the circle
+    assert!(wasm_rtree_cache::get(Coordinate::new(-29.9911, -50.98971))
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        wasm_rtree_cache::remove_containing(Coordinate::new(-29.9911, -50.98971)).unwrap(),
+        0
+    );
+    assert_eq!(wasm_rtree_cache::remove_containing(center).unwrap(), 1);
+
+    // Goes past the north pole, covering every longitude around it
+    let polar = Coordinate::new(89.9, -51.0);
+    wasm_rtree_cache::set_circle("Polar".to_string(), polar, 50_000.0, None).unwrap();
+    assert_eq!(wasm_rtree_cache::get(polar).unwrap().unwrap(), "Polar");
+    assert_eq!(
+        wasm_rtree_cache::get(Coordinate::new(89.95, 129.0))
+            .unwrap()
+            .unwrap(),
+        "Polar"
+    );
+    assert!(wasm_rtree_cache::get(Coordinate::new(89.0, 129.0))
+        .unwrap()
+        .is_none());
+}
+
+#[wasm_bindgen_test]
+pub fn antimeridian_crossing_bbox() {
+    let bbox: BoundingBox = vec![-17.0, -16.0, 179.0, -179.0].try_into().unwrap();
+
+    wasm_rtree_cache::clear().unwrap();
+    let id = wasm_rtree_cache::set_bbox("Fiji".to_string(), bbox.into(), None, None).unwrap();
+
+    assert_eq!(
+        wasm_rtree_cache::get(Coordinate::new(-16.5, 179.5))
+            .unwrap()
+            .unwrap(),
+        "Fiji"
+    );
+    assert_eq!(
+        wasm_rtree_cache::get(Coordinate::new(-16.5, -179.5))
+            .unwrap()
+            .unwrap(),
+        "Fiji"
+    );
+    assert!(wasm_rtree_cache::get(Coordinate::new(-16.5, 0.0))
+        .unwrap()
+        .is_none());
+
+    assert_eq!(wasm_rtree_cache::remove(id).unwrap().unwrap(), "Fiji");
+    assert!(wasm_rtree_cache::get(Coordinate::new(-16.5, -179.5))
+        .unwrap()
+        .is_none());
+}
+
+#[wasm_bindgen_test]
+pub fn antimeridian_crossing_truncation() {
+    let bbox: BoundingBox = vec![-17.0, -16.0, 179.0, -179.0].try_into().unwrap();
+
+    let mut cache = CoordinateCache::new();
+    let result = cache
+        .set_with_max_len(
+            "Fiji".to_string(),
+            bbox,
+            Coordinate::new(-16.5, 179.99).into(),
+            Some(10000.0),
+        )
+        .unwrap();
+
+    let truncated = match result {
+        BoundingBoxSetResult::SetTruncated(truncated) => truncated,
+        _ => panic!("expected the bbox to be truncated"),
+    };
+    assert!(!truncated.is_missing_reference_point);
+    assert!(truncated.new_bbox.north_west.x() > 179.9);
+    assert!(truncated.new_bbox.north_east.x() < -179.9);
+
+    assert_eq!(
+        cache.get(Coordinate::new(-16.5, -179.98).into()).unwrap(),
+        Some("Fiji".to_string())
+    );
+    assert!(cache
+        .get(Coordinate::new(-16.5, -179.5).into())
+        .unwrap()
+        .is_none());
+    assert_eq!(cache.remove_intersecting(bbox), 1);
+    assert!(cache.is_empty());
+}
+
+#[wasm_bindgen_test]
+pub fn nearest_within_tolerance() {
+    let bbox: BoundingBox = vec![-30.01, -30.0, -51.01, -51.0].try_into().unwrap();
+    // ~48m east of the bbox
+    let outside = Coordinate::new(-30.005, -50.9995);
+
+    wasm_rtree_cache::clear().unwrap();
+    wasm_rtree_cache::set_bbox("Near".to_string(), bbox.into(), None, None).unwrap();
+
+    assert!(wasm_rtree_cache::get(outside).unwrap().is_none());
+    assert!(wasm_rtree_cache::get_nearest(outside, 30.0)
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        wasm_rtree_cache::get_nearest(outside, 60.0)
+            .unwrap()
+            .unwrap(),
+        "Near"
+    );
+
+    // ~1.2km away, across the antimeridian
+    let fiji: BoundingBox = vec![-16.5, -16.4, -179.99, -179.9].try_into().unwrap();
+    let across = Coordinate::new(-16.45, 179.999);
+    wasm_rtree_cache::set_bbox("Fiji".to_string(), fiji.into(), None, None).unwrap();
+    assert!(wasm_rtree_cache::get_nearest(across, 1000.0)
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        wasm_rtree_cache::get_nearest(across, 5000.0)
+            .unwrap()
+            .unwrap(),
+        "Fiji"
+    );
+
+    for invalid in [f64::NAN, -1.0, f64::INFINITY] {
+        assert!(wasm_rtree_cache::get_nearest(across, invalid).is_err());
+    }
+}
+
+#[wasm_bindgen_test]
+pub fn nearest_k_orders_by_distance() {
+    let near: BoundingBox = vec![-30.01, -30.0, -51.01, -51.0].try_into().unwrap();
+    let far: BoundingBox = vec![-30.01, -30.0, -50.99, -50.98].try_into().unwrap();
+    let farthest: BoundingBox = vec![-30.01, -30.0, -50.9, -50.89].try_into().unwrap();
+
+    let mut cache = CoordinateCache::new();
+    cache.set("Farthest".to_string(), farthest, None).unwrap();
+    cache.set("Near".to_string(), near, None).unwrap();
+    cache.set("Far".to_string(), far, None).unwrap();
+
+    let nearest = cache.nearest_k(Coordinate::new(-30.005, -51.005).into(), 2);
+
+    assert_eq!(nearest.len(), 2);
+    assert_eq!(nearest[0].data, "Near");
+    assert_eq!(nearest[0].distance_meters, 0.0);
+    assert_eq!(nearest[1].data, "Far");
+    // 0.015° of longitude at 30°S
+    assert!((nearest[1].distance_meters - 1444.0).abs() < 10.0);
+
+    // Just across the antimeridian, and much farther on the same side
+    let across: BoundingBox = vec![-16.5, -16.4, -179.99, -179.9].try_into().unwrap();
+    let same_side: BoundingBox = vec![-16.5, -16.4, 179.0, 179.1].try_into().unwrap();
+    cache.set("Across".to_string(), across, None).unwrap();
+    cache.set("Same side".to_string(), same_side, None).unwrap();
+
+    let nearest = cache.nearest_k(Coordinate::new(-16.45, 179.999).into(), 2);
+    assert_eq!(nearest[0].data, "Across");
+    assert!(nearest[0].distance_meters < 1500.0);
+    assert_eq!(nearest[1].data, "Same side");
+}
+
+#[wasm_bindgen_test]
+pub fn get_all_follows_get_ranking() {
+    let large: BoundingBox = vec![-31.0, -29.0, -52.0, -50.0].try_into().unwrap();
+    let small: BoundingBox = vec![-30.5, -30.4, -51.5, -51.4].try_into().unwrap();
+    let elsewhere: BoundingBox = vec![-20.0, -19.0, -45.0, -44.0].try_into().unwrap();
+    let coordinate = Coordinate::new(-30.45, -51.45).into();
+
+    let mut cache = CoordinateCache::new();
+    cache.set("Large".to_string(), large, None).unwrap();
+    cache.set("Small".to_string(), small, None).unwrap();
+    cache.set("Elsewhere".to_string(), elsewhere, None).unwrap();
+
+    let all = cache.get_all(coordinate);
+
+    assert_eq!(all.len(), 2);
+    assert_eq!(Some(all[0].data.clone()), cache.get(coordinate).unwrap());
+    assert_eq!(all[0].data, "Small");
+    assert_eq!(all[1].data, "Large");
+    assert!(all[0].area_meters < all[1].area_meters);
+    assert!(all[0].center_distance_meters < all[1].center_distance_meters);
+}
+
+#[wasm_bindgen_test]
+pub fn ranking_strategies() {
+    let city: BoundingBox = vec![-31.0, -29.0, -52.0, -50.0].try_into().unwrap();
+    let street: BoundingBox = vec![-30.02, -29.99, -51.05, -50.99].try_into().unwrap();
+    // Closer to the city center than to the street center
+    let coordinate = Coordinate::new(-30.0, -51.0).into();
+
+    let mut cache = CoordinateCache::new();
+    let city_id = cache.set("City".to_string(), city, None).unwrap().id;
+    cache.set("Street".to_string(), street, None).unwrap();
+
+    assert_eq!(cache.get(coordinate).unwrap().unwrap(), "City");
+
+    cache.set_ranking_strategy(RankingStrategy::SmallestArea);
+    assert_eq!(cache.get(coordinate).unwrap().unwrap(), "Street");
+
+    cache.set_ranking_strategy(RankingStrategy::Priority);
+    assert!(cache.set_priority(city_id, 1));
+    assert_eq!(cache.get(coordinate).unwrap().unwrap(), "City");
+
+    // Both entries are usually set within the same millisecond
+    cache.set_ranking_strategy(RankingStrategy::MostRecent);
+    assert_eq!(cache.get(coordinate).unwrap().unwrap(), "Street");
+
+    cache.set_ranking_strategy(RankingStrategy::Custom(Arc::new(|address, _, _| {
+        address.data.len() as f64
+    })));
+    assert_eq!(cache.get(coordinate).unwrap().unwrap(), "City");
+    assert_eq!(cache.get_all(coordinate)[1].data, "Street");
+}
+
+#[wasm_bindgen_test]
+pub fn query_bbox_viewport() {
+    let inside: BoundingBox = vec![-30.02, -30.01, -51.02, -51.01].try_into().unwrap();
+    let crossing: BoundingBox = vec![-30.06, -30.04, -51.06, -51.04].try_into().unwrap();
+    let outside: BoundingBox = vec![-31.0, -30.9, -52.0, -51.9].try_into().unwrap();
+    let viewport: BoundingBox = vec![-30.05, -30.0, -51.05, -51.0].try_into().unwrap();
+
+    let mut cache = CoordinateCache::new();
+    cache.set("Inside".to_string(), inside, None).unwrap();
+    cache.set("Crossing".to_string(), crossing, None).unwrap();
+    cache.set("Outside".to_string(), outside, None).unwrap();
+
+    let intersecting = cache.query_bbox(viewport, BboxQuery::Intersecting);
+    let data = intersecting
+        .iter()
+        .map(|e| e.data.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(data, vec!["Inside", "Crossing"]);
+
+    let within = cache.query_bbox(viewport, BboxQuery::Within);
+    assert_eq!(within.len(), 1);
+    assert_eq!(within[0].data, "Inside");
+}
+
+#[wasm_bindgen_test]
+pub fn stats_count_hits_and_evictions() {
+    let large: BoundingBox = vec![-31.0, -29.0, -52.0, -50.0].try_into().unwrap();
+    let small: BoundingBox = vec![-30.5, -30.4, -51.5, -51.4].try_into().unwrap();
+    let other: BoundingBox = vec![-20.0, -19.0, -45.0, -44.0].try_into().unwrap();
+
+    let mut cache = CoordinateCache::new_with_capacity(2);
+    cache.set("Large".to_string(), large, None).unwrap();
+    let small_id = cache.set("Small".to_string(), small, None).unwrap().id;
+
+    cache.get(Coordinate::new(-29.5, -50.5).into()).unwrap();
+    cache.get(Coordinate::new(-30.45, -51.45).into()).unwrap();
+    cache.get(Coordinate::new(-30.45, -51.45).into()).unwrap();
+    cache.get(Coordinate::new(0.0, 0.0).into()).unwrap();
+    // Evicts "Large", the least recently hit
+    let other_id = cache.set("Other".to_string(), other, None).unwrap().id;
+    // A miss answered by the closest entry, which doesn't count as a hit for it
+    let near_other = Coordinate::new(-18.9995, -44.5).into();
+    assert_eq!(
+        cache.get_nearest(near_other, 100.0).unwrap().unwrap(),
+        "Other"
+    );
+    assert_eq!(cache.hit_count(other_id), Some(0));
+
+    let stats = cache.stats();
+    assert_eq!(stats.gets, 5);
+    assert_eq!(stats.hits, 3);
+    assert_eq!(stats.misses, 2);
+    assert_eq!(stats.overlapping_hits, 2);
+    assert_eq!(stats.nearest_hits, 1);
+    assert_eq!(stats.inserts, 3);
+    assert_eq!(stats.evictions, 1);
+    assert_eq!(cache.hit_count(small_id), Some(2));
+
+    cache.reset_stats();
+    assert_eq!(cache.stats(), Default::default());
+    assert_eq!(cache.hit_count(small_id), Some(0));
+}
+
+#[wasm_bindgen_test]
+pub fn cache_handles_are_independent() {
+    let bbox: BoundingBox = vec![-30.02, -30.01, -51.02, -51.01].try_into().unwrap();
+    let coordinate = Coordinate::new(-30.015, -51.015);
+
+    wasm_rtree_cache::clear().unwrap();
+    let mut addresses = CacheHandle::new();
+    let mut geofences = CacheHandle::new();
+    addresses
+        .set("Address".to_string(), bbox.into(), None, None)
+        .unwrap();
+    geofences
+        .set("Geofence".to_string(), bbox.into(), None, None)
+        .unwrap();
+
+    assert_eq!(addresses.get(coordinate).unwrap().unwrap(), "Address");
+    assert_eq!(geofences.get(coordinate).unwrap().unwrap(), "Geofence");
+    assert!(wasm_rtree_cache::get(coordinate).unwrap().is_none());
+
+    addresses.clear();
+    assert!(addresses.get(coordinate).unwrap().is_none());
+    assert_eq!(geofences.get(coordinate).unwrap().unwrap(), "Geofence");
+
+    let id = addresses
+        .set_circle("Circle".to_string(), coordinate, 100.0, None)
+        .unwrap();
+    assert_eq!(addresses.get(coordinate).unwrap().unwrap(), "Circle");
+    assert!(wasm_rtree_cache::get_by_id(id).unwrap().is_none());
+    assert_eq!(addresses.remove(id).unwrap().unwrap(), "Circle");
+    assert_eq!(geofences.get(coordinate).unwrap().unwrap(), "Geofence");
+}
+
+#[wasm_bindgen_test]
+pub fn set_bbox_with_max_len_describes_truncation() {
+    let bbox: BoundingBox = vec![-31.0, -20.0, -50.0, -40.0].try_into().unwrap();
+    let reference_point = Coordinate::new(-25.1, -45.2);
+    let get =
+        |object: &js_sys::Object, key: &str| js_sys::Reflect::get(object, &key.into()).unwrap();
+
+    wasm_rtree_cache::clear().unwrap();
+    let truncated = wasm_rtree_cache::set_bbox_with_max_len(
+        "Truncated".to_string(),
+        bbox.into(),
+        reference_point,
+        Some(10000.0),
+    )
+    .unwrap();
+
+    assert_eq!(get(&truncated, "truncated").as_bool(), Some(true));
+    assert_eq!(
+        get(&truncated, "is_missing_reference_point").as_bool(),
+        Some(false)
+    );
+    assert!((get(&truncated, "new_width").as_f64().unwrap() - 10000.0).abs() < 100.0);
+    assert!(
+        get(&truncated, "old_area_meters").as_f64() > get(&truncated, "new_area_meters").as_f64()
+    );
+    assert_eq!(
+        wasm_rtree_cache::get(reference_point).unwrap().unwrap(),
+        "Truncated"
+    );
+
+    let not_changed = wasm_rtree_cache::set_bbox_with_max_len(
+        "Kept".to_string(),
+        bbox.into(),
+        reference_point,
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(get(&not_changed, "truncated").as_bool(), Some(false));
+    assert!(get(&not_changed, "width").as_f64().unwrap() > 10000.0);
+
+    // A negative length used to invert the box into one wrapping the globe
+    for max_len in [-1000.0, 0.0, f64::NAN] {
+        assert!(wasm_rtree_cache::set_bbox_with_max_len(
+            "Inverted".to_string(),
+            bbox.into(),
+            reference_point,
+            Some(max_len),
+        )
+        .is_err());
+    }
+    assert!(wasm_rtree_cache::get(Coordinate::new(0.0, 0.0))
+        .unwrap()
+        .is_none());
+}
+
+#[wasm_bindgen_test]
+pub fn invalid_input_returns_structured_error() {
+    let kind = |error: wasm_bindgen::JsValue| {
+        js_sys::Reflect::get(&error, &"kind".into())
+            .unwrap()
+            .as_string()
+    };
+
+    let error = Bbox::from_osm_bbox(vec![-30.0, -29.0]).unwrap_err();
+    assert_eq!(kind(error), Some("InvalidBbox".to_string()));
+
+    let error = wasm_rtree_cache::get(Coordinate::new(f64::NAN, -51.0)).unwrap_err();
+    assert_eq!(kind(error.clone()), Some("InvalidCoordinate".to_string()));
+    assert!(js_sys::Reflect::get(&error, &"input".into())
+        .unwrap()
+        .is_object());
+
+    // The instance keeps working afterwards
+    assert!(wasm_rtree_cache::get(Coordinate::new(-30.0, -51.0)).is_ok());
+}
+
+#[wasm_bindgen_test]
+pub fn validates_coordinates_and_bboxes() {
+    let mut cache = CoordinateCache::<String>::new();
+    let inside = Coordinate::new(-30.5, -51.5).into();
+
+    let out_of_range: BoundingBox = vec![-30.0, 95.0, -52.0, -51.0].try_into().unwrap();
+    assert_eq!(
+        cache
+            .set("Invalid".to_string(), out_of_range, None)
+            .unwrap_err(),
+        ValidationError::LatitudeOutOfRange(95.0)
+    );
+    assert_eq!(
+        cache.get(Coordinate::new(-30.5, -181.0).into()),
+        Err(ValidationError::LongitudeOutOfRange(-181.0))
+    );
+
+    let mut skewed: BoundingBox = vec![-31.0, -30.0, -52.0, -51.0].try_into().unwrap();
+    skewed.north_east.x = -50.0;
+    assert_eq!(
+        cache.set("Skewed".to_string(), skewed, None).unwrap_err(),
+        ValidationError::NotAxisAligned(skewed)
+    );
+
+    let swapped: BoundingBox = vec![-30.0, -31.0, -52.0, -51.0].try_into().unwrap();
+    assert_eq!(
+        cache.set("Swapped".to_string(), swapped, None).unwrap_err(),
+        ValidationError::SwappedCorners(swapped)
+    );
+    assert!(cache.is_empty());
+
+    cache.set_normalize_corners(true);
+    let result = cache.set("Swapped".to_string(), swapped, None).unwrap();
+    assert_eq!(result.bbox.south_west.y(), -31.0);
+    assert_eq!(cache.get(inside).unwrap().unwrap(), "Swapped");
+}
+
+#[wasm_bindgen_test]
+pub fn wasm_coordinates_keep_full_precision() {
+    let osm_bbox = vec![-30.0346812, -30.0346801, -51.2176584, -51.2176573];
+    let bbox: BoundingBox = osm_bbox.clone().try_into().unwrap();
+
+    assert_eq!(BoundingBox::from(Bbox::from(bbox)), bbox);
+    assert_eq!(
+        BoundingBox::from(Bbox::from_osm_bbox(osm_bbox).unwrap()),
+        bbox
+    );
+
+    let coordinate = Coordinate::new(-30.0346812, -51.2176584);
+    assert_eq!(coordinate.lat(), -30.0346812);
+    assert_eq!(coordinate.lon(), -51.2176584);
+}
+
+#[wasm_bindgen_test]
+pub fn changing_precision_resnaps_entries() {
+    let mut cache = CoordinateCache::<String>::new_with_precision(6);
+    let bbox: BoundingBox = vec![-30.123456, -30.0, -51.0, -50.5].try_into().unwrap();
+    cache.set("Entry".to_string(), bbox, None).unwrap();
+    cache.track_changes();
+    cache.take_changes().unwrap();
+
+    cache.set_float_precision(3);
+    assert_eq!(cache.float_precision(), 3);
+
+    let viewport: BoundingBox = vec![-31.0, -29.0, -52.0, -50.0].try_into().unwrap();
+    let entries = cache.query_bbox(viewport, BboxQuery::Within);
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].bbox.south_west.y(), -30.123);
+    assert_eq!(cache.take_changes().unwrap().upserted.len(), 1);
+}
+
+#[wasm_bindgen_test]
+pub fn init_configures_precision() {
+    let config = |options: &[(&str, f64)]| {
+        let object = js_sys::Object::new();
+        for (key, value) in options {
+            js_sys::Reflect::set(&object, &(*key).into(), &(*value).into()).unwrap();
+        }
+        wasm_bindgen::JsValue::from(object)
+    };
+    let bbox: BoundingBox = vec![-30.000014, -30.000011, -51.1, -51.0]
+        .try_into()
+        .unwrap();
+    let outside_at_6_decimals = Coordinate::new(-30.000009, -51.05);
+
+    assert!(wasm_rtree_cache::init(config(&[("float_precision", 40.0)])).is_err());
+    assert!(wasm_rtree_cache::init(config(&[("float_precision", 14.0)])).is_err());
+    assert!(wasm_rtree_cache::init(config(&[("max_entries", -1.0)])).is_err());
+
+    wasm_rtree_cache::clear().unwrap();
+    wasm_rtree_cache::init(config(&[("float_precision", 6.0)])).unwrap();
+    wasm_rtree_cache::set_bbox("Entry".to_string(), bbox.into(), None, None).unwrap();
+    assert!(wasm_rtree_cache::get(outside_at_6_decimals)
+        .unwrap()
+        .is_none());
+
+    // Back to the default, the entry is re-snapped to 5 decimals
+    wasm_rtree_cache::init(config(&[("float_precision", 5.0)])).unwrap();
+    assert_eq!(
+        wasm_rtree_cache::get(outside_at_6_decimals).unwrap(),
+        Some("Entry".to_string())
+    );
+    wasm_rtree_cache::clear().unwrap();
+}
+
+#[wasm_bindgen_test]
+pub fn get_many_looks_up_interleaved_coordinates() {
+    let first: BoundingBox = vec![-30.02, -30.01, -51.02, -51.01].try_into().unwrap();
+    let second: BoundingBox = vec![-29.02, -29.01, -50.02, -50.01].try_into().unwrap();
+    let twin: BoundingBox = vec![-28.02, -28.01, -49.02, -49.01].try_into().unwrap();
+
+    wasm_rtree_cache::clear().unwrap();
+    wasm_rtree_cache::set_bbox("First".to_string(), first.into(), None, None).unwrap();
+    wasm_rtree_cache::set_bbox("Second".to_string(), second.into(), None, None).unwrap();
+    wasm_rtree_cache::set_bbox("First".to_string(), twin.into(), None, None).unwrap();
+
+    let trip = [
+        -30.015, -51.015, 0.0, 0.0, -29.015, -50.015, -30.012, -51.012, -28.015, -49.015,
+    ];
+    let found = wasm_rtree_cache::get_many(&trip).unwrap();
+    let found = found.iter().map(|v| v.as_string()).collect::<Vec<_>>();
+    assert_eq!(
+        found,
+        vec![
+            Some("First".to_string()),
+            None,
+            Some("Second".to_string()),
+            Some("First".to_string()),
+            Some("First".to_string()),
+        ]
+    );
+
+    let indexed = wasm_rtree_cache::get_many_indexed(&trip).unwrap();
+    let get = |key: &str| js_sys::Reflect::get(&indexed, &key.into()).unwrap();
+    let payloads = js_sys::Array::from(&get("payloads"));
+    assert_eq!(payloads.length(), 2);
+    assert_eq!(payloads.get(0).as_string().unwrap(), "First");
+    let indices = js_sys::Int32Array::from(get("indices")).to_vec();
+    assert_eq!(indices, vec![0, -1, 1, 0, 0]);
+
+    assert!(wasm_rtree_cache::get_many(&[-30.0]).is_err());
+    assert!(wasm_rtree_cache::get_many(&[-30.0, 200.0]).is_err());
+    wasm_rtree_cache::clear().unwrap();
+}
+
+#[wasm_bindgen_test]
+pub fn invalid_ttls_are_rejected() {
+    let bbox: BoundingBox = vec![-30.02, -30.01, -51.19, -51.18].try_into().unwrap();
+
+    wasm_rtree_cache::clear().unwrap();
+    for invalid in [f64::NAN, -1.0, f64::INFINITY] {
+        assert!(
+            wasm_rtree_cache::set_bbox("Dead".to_string(), bbox.into(), None, Some(invalid))
+                .is_err()
+        );
+    }
+    assert_eq!(wasm_rtree_cache::memory_usage().unwrap(), 0);
+}
+
+#[wasm_bindgen_test]
+pub fn entries_over_the_limits_are_rejected() {
+    let bbox: BoundingBox = vec![-30.02, -30.01, -51.19, -51.18].try_into().unwrap();
+
+    let mut cache = CoordinateCache::<String>::new();
+    cache.set_max_memory_bytes(Some(10));
+    assert!(matches!(
+        cache.set("Too large".to_string(), bbox, None).unwrap_err(),
+        ValidationError::ExceedsLimits(_)
+    ));
+
+    cache.set_max_memory_bytes(None);
+    cache.set_max_entries(Some(0));
+    assert!(matches!(
+        cache.set("No room".to_string(), bbox, None).unwrap_err(),
+        ValidationError::ExceedsLimits(_)
+    ));
+    assert!(cache.is_empty());
+    assert_eq!(cache.stats().inserts, 0);
+}
+
+#[wasm_bindgen_test]
+pub fn invalid_shapes_are_rejected() {
+    let mut cache = CoordinateCache::<String>::new();
+    let center = Coordinate::new(-30.0, -51.0).into();
+
+    assert!(matches!(
+        cache.set_circle(
+            "Circle".to_string(),
+            Coordinate::new(f64::NAN, -51.0).into(),
+            10.0,
+            None
+        ),
+        Err(ValidationError::NonFinite(_))
+    ));
+    assert_eq!(
+        cache
+            .set_circle("Circle".to_string(), center, f64::INFINITY, None)
+            .unwrap_err(),
+        ValidationError::InvalidDistance(f64::INFINITY)
+    );
+
+    let empty = geo::Polygon::new(Vec::<(f64, f64)>::new().into(), vec![]);
+    assert_eq!(
+        cache
+            .set_polygon("Empty".to_string(), empty, None)
+            .unwrap_err(),
+        ValidationError::EmptyPolygon
+    );
+    let outside = geo::Polygon::new(vec![(0.0, 0.0), (200.0, 0.0), (0.0, 1.0)].into(), vec![]);
+    assert!(cache
+        .set_polygon("Outside".to_string(), outside, None)
+        .is_err());
+    assert!(cache.is_empty());
+
+    let id = cache
+        .set_circle("Circle".to_string(), center, 10.0, None)
+        .unwrap();
+    assert_eq!(cache.get_by_id(id).unwrap(), "Circle");
+}